@@ -33,4 +33,75 @@ bench({
   },
 });
 
+// `wide_object` in benches/encode.rs measures the same case under node, also
+// against the code from before each field was read once
+bench({
+  name: "encode 10k fields",
+  runs: 3,
+  func(b) {
+    const wide: Record<string, number> = {};
+    for (let i = 0; i < 10000; i++) {
+      wide[`field${i}`] = i;
+    }
+    b.start();
+    for (let i = 0; i < 100; i++) {
+      encode(wide);
+    }
+    b.stop();
+  },
+});
+
+bench({
+  name: "encode 200k fields",
+  runs: 3,
+  func(b) {
+    const wide: Record<string, number> = {};
+    for (let i = 0; i < 200000; i++) {
+      wide[`field${i}`] = i;
+    }
+    b.start();
+    encode(wide);
+    b.stop();
+  },
+});
+
+bench({
+  name: "encode 1M-element array",
+  runs: 3,
+  func(b) {
+    const array = Array.from({ length: 1000000 }, (_, i) => i);
+    b.start();
+    encode({ array });
+    b.stop();
+  },
+});
+
+bench({
+  name: "encode 1M-field indexed object",
+  runs: 3,
+  func(b) {
+    // the same numbers, read field by field through Reflect
+    const indexed = Object.fromEntries(
+      Array.from({ length: 1000000 }, (_, i) => [i, i]),
+    );
+    b.start();
+    encode({ indexed });
+    b.stop();
+  },
+});
+
+bench({
+  name: "encode 50MB binary",
+  runs: 3,
+  func(b) {
+    const blob = new Uint8Array(50 * 1024 * 1024);
+    const before = Deno.memoryUsage().rss;
+    b.start();
+    encode({ blob });
+    b.stop();
+    const growth = (Deno.memoryUsage().rss - before) / (1024 * 1024);
+    console.log(`rss growth: ${growth.toFixed(1)}MB`);
+  },
+});
+
 await runBenchmarks();
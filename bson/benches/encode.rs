@@ -0,0 +1,80 @@
+//! Encoder benchmarks, run with `cargo bench --target wasm32-unknown-unknown`
+//! (see `.cargo/config.toml` for the runner), the encoder needs a javascript runtime.
+#![cfg(target_arch = "wasm32")]
+
+use mango_bson::encode;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::{wasm_bindgen_bench, Criterion};
+
+/// Documents made of extended JSON wrappers, whose keys (`$numberLong`,
+/// `pattern`, `subType`, ...) are read for every value
+#[wasm_bindgen_bench]
+fn wrapped_documents(c: &mut Criterion) {
+    let document = concat!(
+        r#"{"n": {"$numberLong": "5"}, "i": {"$numberInt": "7"},"#,
+        r#""d": {"$date": {"$numberLong": "1565545664000"}},"#,
+        r#""r": {"$regularExpression": {"pattern": "^a", "options": "i"}},"#,
+        r#""b": {"$binary": {"base64": "AAEC", "subType": "00"}},"#,
+        r#""t": {"$timestamp": {"t": 1, "i": 2}}}"#
+    );
+    let documents = vec![document; 1000].join(",");
+    let value = js_sys::JSON::parse(&format!("[{}]", documents)).unwrap();
+    c.bench_function("encode 1k wrapped documents", |b| {
+        b.iter(|| encode(&value).unwrap())
+    });
+}
+
+/// A single property read, with a fresh key string or with one created once,
+/// as `keys` caches them
+#[wasm_bindgen_bench]
+fn property_keys(c: &mut Criterion) {
+    let value = js_sys::JSON::parse(r#"{"$numberLong": "5"}"#).unwrap();
+    c.bench_function("read with a new key", |b| {
+        b.iter(|| js_sys::Reflect::get(&value, &JsValue::from_str("$numberLong")).unwrap())
+    });
+    let key = JsValue::from_str("$numberLong");
+    c.bench_function("read with a cached key", |b| {
+        b.iter(|| js_sys::Reflect::get(&value, &key).unwrap())
+    });
+}
+
+/// A wide flat object, along with the reads its fields take on their own
+#[wasm_bindgen_bench]
+fn flat_object(c: &mut Criterion) {
+    let value = js_sys::Function::new_no_args(
+        "const wide = {}; for (let i = 0; i < 200000; i++) wide[`field${i}`] = i; return wide;",
+    )
+    .call0(&JsValue::NULL)
+    .unwrap();
+    c.bench_function("encode 200k-field flat object", |b| {
+        b.iter(|| encode(&value).unwrap())
+    });
+    c.bench_function("read 200k fields", |b| {
+        b.iter(|| {
+            let keys = js_sys::Reflect::own_keys(&value).unwrap();
+            keys.iter()
+                .map(|key| (key.as_string(), js_sys::Reflect::get(&value, &key).unwrap()))
+                .collect::<Vec<_>>()
+        })
+    });
+}
+
+/// A 10k-field object, of plain numbers or of extended JSON wrappers, whose fields
+/// are each read once
+#[wasm_bindgen_bench]
+fn wide_object(c: &mut Criterion) {
+    let wide = |field: &str| {
+        let fields: Vec<String> = (0..10_000)
+            .map(|i| format!(r#""field{}": {}"#, i, field))
+            .collect();
+        js_sys::JSON::parse(&format!("{{{}}}", fields.join(","))).unwrap()
+    };
+    let plain = wide("5");
+    c.bench_function("encode 10k plain fields", |b| {
+        b.iter(|| encode(&plain).unwrap())
+    });
+    let wrapped = wide(r#"{"$binary": {"base64": "AAEC", "subType": "00"}}"#);
+    c.bench_function("encode 10k wrapped fields", |b| {
+        b.iter(|| encode(&wrapped).unwrap())
+    });
+}
@@ -28,6 +28,10 @@ export function Int64(val: number): types.Int64 {
   return { $numberLong: String(val) };
 }
 
+export function Decimal128(val: string): types.Decimal128 {
+  return { $numberDecimal: val };
+}
+
 export function Regex(val: RegExp): types.RegularExpression {
   return { $regularExpression: { pattern: val.source, options: val.flags } };
 }
@@ -38,7 +42,7 @@ export function Timestamp(t: number, i: number): types.Timestamp {
 
 export function Binary(
   payload: Uint8Array,
-  subType: types.BinarySubtype,
+  subType: types.BinarySubtype | types.BinarySubtypeName,
 ): types.Binary {
   const output: string = Array.from(payload)
     .map((val): string => String.fromCharCode(val))
@@ -47,6 +51,32 @@ export function Binary(
   return { $binary: { base64, subType } };
 }
 
+export function UUID(uuid: string): types.UUID {
+  return { $uuid: uuid };
+}
+
+export function Code(code: string, scope?: types.BsonObject): types.Code {
+  return scope === undefined ? { $code: code } : { $code: code, $scope: scope };
+}
+
+export function BsonSymbol(val: string): types.BsonSymbol {
+  return { $symbol: val };
+}
+
+export function Undefined(): types.Undefined {
+  return { $undefined: true };
+}
+
+export function DBRef(
+  collection: string,
+  id: types.BsonField,
+  db?: string,
+): types.DBRef {
+  return db === undefined
+    ? { $ref: collection, $id: id }
+    : { $ref: collection, $id: id, $db: db };
+}
+
 export function MaxKey(): types.MaxKey {
   return { $maxKey: 1 };
 }
@@ -55,6 +85,13 @@ export function MinKey(): types.MinKey {
   return { $minKey: 1 };
 }
 
+/**
+ * Encode an object into a BSON document.
+ *
+ * Fields are written in `Reflect.ownKeys` order: integer-like keys first in
+ * ascending order, then every other key in insertion order. Use a `Map` when
+ * the exact field order matters, its entries are written in insertion order.
+ */
 export function encode(object: types.BsonObject): Uint8Array {
   return toBsonDocument(object);
 }
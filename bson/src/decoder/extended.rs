@@ -0,0 +1,167 @@
+use bson::{oid::ObjectId, Bson, DateTime};
+use chrono::SecondsFormat;
+use wasm_bindgen::JsValue;
+
+use crate::keys::{self, Key};
+use crate::Result;
+
+// Wrappers with several keys set them in the order of the extended JSON spec,
+// e.g. `base64` before `subType`: javascript objects enumerate (non integer-like)
+// keys in insertion order, so `JSON.stringify` output matches reference extended
+// JSON byte for byte.
+
+/// Create a `{key: value}` JS object
+fn wrap(key: &'static Key, value: &JsValue) -> Result<JsValue> {
+    let object = js_sys::Object::new();
+    keys::set(&object, key, value)?;
+    Ok(object.into())
+}
+
+/// `{“$oid”: ”<oid>”}`
+pub fn oid(oid: &ObjectId) -> Result<JsValue> {
+    wrap(&keys::OID, &JsValue::from_str(&oid.to_hex()))
+}
+
+/// The creation time embedded in the first 4 bytes of an ObjectId, as Unix seconds
+pub fn oid_timestamp(oid: &ObjectId) -> i64 {
+    let bytes = oid.bytes();
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as i64
+}
+
+/// 0000-01-01T00:00:00Z, the first date with a 4-digit ISO year
+const MIN_ISO_MILLIS: i64 = -62_167_219_200_000;
+/// 10000-01-01T00:00:00Z, the first date past the last 4-digit ISO year
+const MAX_ISO_MILLIS: i64 = 253_402_300_800_000;
+
+/// `{"$date": {"$numberLong": "<millis>"}}`
+pub fn date(date: &DateTime) -> Result<JsValue> {
+    let ms = long(date.timestamp_millis())?;
+    wrap(&keys::DATE, &ms)
+}
+
+/// `{"$date": "<ISO-8601>"}` (relaxed mode)
+/// <ISO-8601>: An RFC-3339 UTC date with the given precision, e.g. "2020-01-01T00:00:00.000Z"
+///     with `SecondsFormat::Millis` as in relaxed mode.
+/// Dates outside of the years 0000 to 9999 have no 4-digit year to write, they
+/// fall back to the canonical `{"$date": {"$numberLong": "<millis>"}}`.
+pub fn iso_date(date: &DateTime, precision: SecondsFormat) -> Result<JsValue> {
+    if !(MIN_ISO_MILLIS..MAX_ISO_MILLIS).contains(&date.timestamp_millis()) {
+        return self::date(date);
+    }
+    let iso = date.to_chrono().to_rfc3339_opts(precision, true);
+    wrap(&keys::DATE, &JsValue::from_str(&iso))
+}
+
+/// `{"$numberDouble": "<decimal string>"}`
+/// Non-finite values are spelled "Infinity", "-Infinity" and "NaN".
+pub fn double(n: f64) -> Result<JsValue> {
+    let n = if n.is_nan() {
+        String::from("NaN")
+    } else if n.is_infinite() {
+        String::from(if n > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        // `{:?}` always keeps a fractional part, e.g. "1.0" and "-0.0"
+        format!("{:?}", n)
+    };
+    wrap(&keys::NUMBER_DOUBLE, &JsValue::from_str(&n))
+}
+
+/// `{"$numberInt": "<number>"}`
+pub fn int(n: i32) -> Result<JsValue> {
+    wrap(&keys::NUMBER_INT, &JsValue::from_str(&n.to_string()))
+}
+
+/// `{"$numberLong": "<number>"}`
+pub fn long(n: i64) -> Result<JsValue> {
+    wrap(&keys::NUMBER_LONG, &JsValue::from_str(&n.to_string()))
+}
+
+/// `{"$regularExpression": {"pattern": "<regexPattern>", "options": "<options>"}}`
+pub fn regex(regex: &bson::Regex) -> Result<JsValue> {
+    let object = js_sys::Object::new();
+    keys::set(&object, &keys::PATTERN, &JsValue::from_str(&regex.pattern))?;
+    keys::set(&object, &keys::OPTIONS, &JsValue::from_str(&regex.options))?;
+    wrap(&keys::REGULAR_EXPRESSION, &object)
+}
+
+/// `{"$binary": {"base64": <payload>, "subType": <t>}}`
+/// <t>: Always a two-character hex string.
+pub fn binary(binary: &bson::Binary) -> Result<JsValue> {
+    let object = js_sys::Object::new();
+    keys::set(
+        &object,
+        &keys::BASE64,
+        &JsValue::from_str(&base64::encode(&binary.bytes)),
+    )?;
+    keys::set(
+        &object,
+        &keys::SUB_TYPE,
+        &JsValue::from_str(&format!("{:02x}", u8::from(binary.subtype))),
+    )?;
+    wrap(&keys::BINARY, &object)
+}
+
+/// `{"$numberDecimal": "<number>"}`
+/// <number>: The `Display` form of the decimal, which already uses the spec spellings
+///     "NaN", "Infinity" and "-Infinity" for special values, so it parses back unchanged.
+pub fn decimal(decimal: &bson::Decimal128) -> Result<JsValue> {
+    wrap(
+        &keys::NUMBER_DECIMAL,
+        &JsValue::from_str(&decimal.to_string()),
+    )
+}
+
+/// `{"$timestamp": {"t": <t>, "i": <i>}}`
+/// <t>: The seconds since the epoch, <i>: the increment, both plain numbers.
+///     `t` is the raw seconds, never milliseconds, matching what the encoder reads.
+pub fn timestamp(timestamp: &bson::Timestamp) -> Result<JsValue> {
+    let object = js_sys::Object::new();
+    keys::set(&object, &keys::T, &JsValue::from_f64(timestamp.time as f64))?;
+    keys::set(
+        &object,
+        &keys::I,
+        &JsValue::from_f64(timestamp.increment as f64),
+    )?;
+    wrap(&keys::TIMESTAMP, &object)
+}
+
+/// `{"$code": "<javascript>"}`, with a sibling `"$scope": <scope>` when there is a scope
+/// <scope>: The already decoded scope document.
+pub fn code(code: &str, scope: Option<&JsValue>) -> Result<JsValue> {
+    let object = wrap(&keys::CODE, &JsValue::from_str(code))?;
+    if let Some(scope) = scope {
+        keys::set(&object, &keys::SCOPE, scope)?;
+    }
+    Ok(object)
+}
+
+/// `{"$minKey": 1}` or `{"$maxKey": 1}`
+pub fn key(marker: &'static Key) -> Result<JsValue> {
+    wrap(marker, &JsValue::from_f64(1.0))
+}
+
+/// `{"$symbol": "<string>"}`
+pub fn symbol(symbol: &str) -> Result<JsValue> {
+    wrap(&keys::SYMBOL, &JsValue::from_str(symbol))
+}
+
+/// `{"$undefined": true}`
+pub fn undefined() -> Result<JsValue> {
+    wrap(&keys::UNDEFINED, &JsValue::TRUE)
+}
+
+/// `{"$dbPointer": {"$ref": "<namespace>", "$id": {"$oid": "<oid>"}}}`
+pub fn db_pointer(pointer: &bson::DbPointer) -> Result<JsValue> {
+    // bson doesn't expose the fields of a DbPointer, only its extended JSON form
+    let json = Bson::DbPointer(pointer.clone()).into_canonical_extjson();
+    let pointer = &json["$dbPointer"];
+    let (namespace, id) = match (pointer["$ref"].as_str(), pointer["$id"]["$oid"].as_str()) {
+        (Some(namespace), Some(id)) => (namespace, id),
+        _ => return Err(format!("unexpected $dbPointer form: {}", json).into()),
+    };
+    let id = ObjectId::parse_str(id).map_err(|err| err.to_string())?;
+    let object = js_sys::Object::new();
+    keys::set(&object, &keys::REF, &JsValue::from_str(namespace))?;
+    keys::set(&object, &keys::ID, &oid(&id)?)?;
+    wrap(&keys::DB_POINTER, &object)
+}
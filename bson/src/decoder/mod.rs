@@ -0,0 +1,48 @@
+use bson::Bson;
+use wasm_bindgen::JsValue;
+
+use crate::Result;
+
+mod extended;
+mod object;
+mod options;
+mod stream;
+mod value;
+
+pub use extended::oid_timestamp;
+pub(crate) use extended::{binary, oid};
+pub use options::{DateFormat, DecodeOptions};
+pub use stream::BsonStreamDecoder;
+
+/// Extended JSON output mode, matching MongoDB's two serialization modes
+/// For reference: https://docs.mongodb.com/manual/reference/mongodb-extended-json/
+///
+/// Only a few variants differ between the two modes, everything else is always
+/// emitted with its canonical wrapper:
+/// - `Int32`, `Int64`: `{"$numberInt": "<n>"}` / `{"$numberLong": "<n>"}` vs a plain JS number
+/// - `Double`: `{"$numberDouble": "<n>"}` vs a plain JS number (non-finite values stay wrapped)
+/// - `DateTime`: `{"$date": {"$numberLong": "<millis>"}}` vs `{"$date": "<ISO-8601>"}`,
+///   unless `DecodeOptions::date_format` picks another format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtJsonMode {
+    /// Type preserving output, every number is wrapped
+    Canonical,
+    /// Human friendly output, numbers are native and dates are ISO-8601 strings
+    Relaxed,
+}
+
+/// Decode a BSON value into a JsValue, using the same canonical extended JSON
+/// conventions the encoder understands (`{"$oid": ...}`, `{"$date": ...}`, ...)
+pub fn decode(bson: &Bson) -> Result<JsValue> {
+    decode_with(bson, ExtJsonMode::Canonical)
+}
+
+/// Decode a BSON value into a JsValue using the given extended JSON mode
+pub fn decode_with(bson: &Bson, mode: ExtJsonMode) -> Result<JsValue> {
+    decode_with_options(bson, &DecodeOptions::new(mode))
+}
+
+/// Decode a BSON value into a JsValue, see `DecodeOptions` for what can be tuned
+pub fn decode_with_options(bson: &Bson, options: &DecodeOptions) -> Result<JsValue> {
+    value::decode_value(bson, options)
+}
@@ -0,0 +1,55 @@
+use bson::{Bson, Document};
+use wasm_bindgen::JsValue;
+
+use super::{value, DecodeOptions};
+use crate::Result;
+
+/// Decode a BSON document into a plain JS object, or a `Map` when
+/// `options.preserve_order` is set
+pub fn document(document: &Document, options: &DecodeOptions) -> Result<JsValue> {
+    if options.preserve_order {
+        let map = js_sys::Map::new();
+        for (key, val) in document {
+            map.set(&JsValue::from_str(key), &value::decode_value(val, options)?);
+        }
+        return Ok(map.into());
+    }
+    let object = js_sys::Object::new();
+    for (key, val) in document {
+        let val = value::decode_value(val, options)?;
+        if key == "__proto__" {
+            // assigning `__proto__` would replace the prototype instead
+            own_property(&object, key, &val)?;
+        } else {
+            js_sys::Reflect::set(&object, &JsValue::from_str(key), &val)?;
+        }
+    }
+    Ok(object.into())
+}
+
+/// Define `key` as an own enumerable data property, like `JSON.parse` does, even
+/// when the prototype chain has a setter for it
+fn own_property(object: &js_sys::Object, key: &str, val: &JsValue) -> Result<()> {
+    let descriptor = js_sys::Object::new();
+    for (name, value) in [
+        ("value", val),
+        ("writable", &JsValue::TRUE),
+        ("enumerable", &JsValue::TRUE),
+        ("configurable", &JsValue::TRUE),
+    ]
+    .iter()
+    {
+        js_sys::Reflect::set(&descriptor, &JsValue::from_str(name), value)?;
+    }
+    js_sys::Reflect::define_property(object, &JsValue::from_str(key), &descriptor)?;
+    Ok(())
+}
+
+/// Decode a BSON array into a JS array
+pub fn array(array: &[Bson], options: &DecodeOptions) -> Result<JsValue> {
+    let output = js_sys::Array::new_with_length(array.len() as u32);
+    for (i, val) in array.iter().enumerate() {
+        output.set(i as u32, value::decode_value(val, options)?);
+    }
+    Ok(output.into())
+}
@@ -0,0 +1,69 @@
+use super::ExtJsonMode;
+
+/// How `Bson::DateTime` values are rendered in the `$date` wrapper
+///
+/// The RFC-3339 formats only apply to the years 0000 to 9999, other dates are
+/// always rendered as `EpochMillis`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `{"$date": {"$numberLong": "<millis>"}}`, the canonical form
+    EpochMillis,
+    /// `{"$date": "<RFC-3339>"}` in UTC, with only as many fractional digits as needed,
+    /// e.g. "2020-01-01T00:00:00Z" or "2020-01-01T00:00:00.250Z"
+    Rfc3339,
+    /// `{"$date": "<RFC-3339>"}` in UTC, always with millisecond precision,
+    /// e.g. "2020-01-01T00:00:00.000Z", the relaxed form
+    Rfc3339WithMillis,
+}
+
+/// Options controlling how BSON values are decoded into JsValues
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Extended JSON output mode
+    pub mode: ExtJsonMode,
+    /// Date rendering, when `None` it follows `mode`: `EpochMillis` for canonical
+    /// output and `Rfc3339WithMillis` for relaxed output
+    pub date_format: Option<DateFormat>,
+    /// Decode documents into `Map`s instead of plain objects, keeping the exact
+    /// field order
+    ///
+    /// Javascript objects always enumerate integer-like keys (`"0"`, `"1"`, ...)
+    /// first, in ascending order, so `{"b": .., "1": ..}` comes back as
+    /// `{"1": .., "b": ..}` from a plain object. A `Map` keeps the stored order and
+    /// encodes back to the same document, but `JSON.stringify` turns it into `{}`
+    /// and it is read with `map.get(key)` instead of `object.key`, so it is off by
+    /// default.
+    pub preserve_order: bool,
+    /// Decode generic binary (subtype `0x00`) into a `Uint8Array` holding a copy of
+    /// the bytes instead of a `{"$binary": ...}` wrapper; other subtypes keep the
+    /// wrapper so their subtype isn't lost. The encoder reads a `Uint8Array` back
+    /// as generic binary, but the output is no longer extended JSON, so it's off
+    /// by default.
+    pub binary_as_typed_array: bool,
+}
+
+impl DecodeOptions {
+    /// Options for the given mode, with everything else following it
+    pub fn new(mode: ExtJsonMode) -> Self {
+        DecodeOptions {
+            mode,
+            date_format: None,
+            preserve_order: false,
+            binary_as_typed_array: false,
+        }
+    }
+
+    /// The date format in effect
+    pub(crate) fn date_format(&self) -> DateFormat {
+        self.date_format.unwrap_or(match self.mode {
+            ExtJsonMode::Canonical => DateFormat::EpochMillis,
+            ExtJsonMode::Relaxed => DateFormat::Rfc3339WithMillis,
+        })
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        DecodeOptions::new(ExtJsonMode::Canonical)
+    }
+}
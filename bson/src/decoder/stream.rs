@@ -0,0 +1,103 @@
+use std::io::Read;
+
+use bson::{Bson, Document};
+use wasm_bindgen::JsValue;
+
+use super::{value, DecodeOptions};
+use crate::{EncodeError, Error, Result};
+
+/// Decode a stream of concatenated BSON documents one at a time, e.g. the
+/// documents of a cursor reply
+///
+/// Every document is read using its little-endian `int32` length prefix, so only
+/// one document is buffered at a time. Data ending in the middle of a document,
+/// or of its length prefix, is reported as an error for that last document
+/// instead of being dropped. The decoder stops after the first error since the
+/// position of the next document can't be trusted anymore.
+pub struct BsonStreamDecoder<R: Read> {
+    reader: R,
+    options: DecodeOptions,
+    done: bool,
+}
+
+impl<R: Read> BsonStreamDecoder<R> {
+    /// Decode the documents of `reader` into canonical extended JSON
+    pub fn new(reader: R) -> Self {
+        BsonStreamDecoder::with_options(reader, DecodeOptions::default())
+    }
+
+    /// Decode the documents of `reader` with the given options
+    pub fn with_options(reader: R, options: DecodeOptions) -> Self {
+        BsonStreamDecoder {
+            reader,
+            options,
+            done: false,
+        }
+    }
+
+    /// Read the raw bytes of the next document, `None` at the end of the stream
+    fn next_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut bytes = Vec::with_capacity(4);
+        (&mut self.reader)
+            .take(4)
+            .read_to_end(&mut bytes)
+            .map_err(read_error)?;
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        if bytes.len() < 4 {
+            return Err(truncated(4, bytes.len()));
+        }
+        let length = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        // a length below the 4 bytes already read is left to the parser to reject
+        let length = (length as i64).max(4) as u64;
+        (&mut self.reader)
+            .take(length - 4)
+            .read_to_end(&mut bytes)
+            .map_err(read_error)?;
+        if (bytes.len() as u64) < length {
+            return Err(truncated(length, bytes.len()));
+        }
+        Ok(Some(bytes))
+    }
+}
+
+impl<R: Read> Iterator for BsonStreamDecoder<R> {
+    type Item = Result<JsValue>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_bytes().and_then(|bytes| match bytes {
+            Some(bytes) => {
+                let document =
+                    Document::from_reader(&bytes[..]).map_err(EncodeError::Deserialize)?;
+                value::decode_value(&Bson::Document(document), &self.options).map(Some)
+            }
+            None => Ok(None),
+        });
+        match result {
+            Ok(Some(value)) => Some(Ok(value)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn read_error(err: std::io::Error) -> Error {
+    Error::Message(format!("error reading stream: {}", err))
+}
+
+fn truncated(expected: u64, found: usize) -> Error {
+    Error::Message(format!(
+        "truncated document: expected {} bytes, found {}",
+        expected, found
+    ))
+}
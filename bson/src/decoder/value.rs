@@ -0,0 +1,57 @@
+use bson::{spec::BinarySubtype, Bson};
+use chrono::SecondsFormat;
+use wasm_bindgen::JsValue;
+
+use super::{extended, object, DateFormat, DecodeOptions, ExtJsonMode};
+use crate::{keys, Result};
+
+/// Decode a generic BSON value, mapping plain values to their javascript counterparts
+/// and everything else to its extended JSON wrapper
+pub(crate) fn decode_value(bson: &Bson, options: &DecodeOptions) -> Result<JsValue> {
+    let mode = options.mode;
+    Ok(match bson {
+        Bson::String(s) => JsValue::from_str(s),
+        Bson::Boolean(b) => JsValue::from_bool(*b),
+        Bson::Null => JsValue::NULL,
+        Bson::Document(document) => object::document(document, options)?,
+        Bson::Array(array) => object::array(array, options)?,
+        Bson::ObjectId(oid) => extended::oid(oid)?,
+        Bson::DateTime(date) => match options.date_format() {
+            DateFormat::EpochMillis => extended::date(date)?,
+            DateFormat::Rfc3339 => extended::iso_date(date, SecondsFormat::AutoSi)?,
+            DateFormat::Rfc3339WithMillis => extended::iso_date(date, SecondsFormat::Millis)?,
+        },
+        Bson::Double(n) => match mode {
+            ExtJsonMode::Relaxed if n.is_finite() => JsValue::from_f64(*n),
+            _ => extended::double(*n)?,
+        },
+        Bson::Int32(n) => match mode {
+            ExtJsonMode::Canonical => extended::int(*n)?,
+            ExtJsonMode::Relaxed => JsValue::from_f64(*n as f64),
+        },
+        Bson::Int64(n) => match mode {
+            ExtJsonMode::Canonical => extended::long(*n)?,
+            // may lose precision above 2^53, as with any relaxed extended JSON
+            ExtJsonMode::Relaxed => JsValue::from_f64(*n as f64),
+        },
+        Bson::RegularExpression(regex) => extended::regex(regex)?,
+        Bson::Binary(binary)
+            if options.binary_as_typed_array && binary.subtype == BinarySubtype::Generic =>
+        {
+            js_sys::Uint8Array::from(binary.bytes.as_slice()).into()
+        }
+        Bson::Binary(binary) => extended::binary(binary)?,
+        Bson::Decimal128(decimal) => extended::decimal(decimal)?,
+        Bson::Timestamp(timestamp) => extended::timestamp(timestamp)?,
+        Bson::JavaScriptCode(code) => extended::code(code, None)?,
+        Bson::JavaScriptCodeWithScope(code) => {
+            let scope = object::document(&code.scope, options)?;
+            extended::code(&code.code, Some(&scope))?
+        }
+        Bson::MinKey => extended::key(&keys::MIN_KEY)?,
+        Bson::MaxKey => extended::key(&keys::MAX_KEY)?,
+        Bson::Symbol(symbol) => extended::symbol(symbol)?,
+        Bson::Undefined => extended::undefined()?,
+        Bson::DbPointer(pointer) => extended::db_pointer(pointer)?,
+    })
+}
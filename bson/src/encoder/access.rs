@@ -0,0 +1,133 @@
+use std::convert::TryFrom;
+
+use bson::Bson;
+use serde_json::Value;
+use wasm_bindgen::JsValue;
+
+use super::object::{self, Fields};
+use super::{value, Context, EncodeError, Result};
+use crate::keys::{self, Key};
+
+/// Read access to the value of an extended JSON wrapper, so its handlers don't
+/// depend on a javascript runtime: they run on `JsValue`s when encoding from
+/// javascript and on `serde_json::Value`s in `encode_json`, which the native test
+/// suite exercises without WASM
+pub(crate) trait ValueAccess: Sized {
+    /// The number, if the value is one
+    fn as_f64(&self) -> Option<f64>;
+    /// The string, if the value is one
+    fn as_string(&self) -> Option<String>;
+    /// The boolean, if the value is one
+    fn as_bool(&self) -> Option<bool>;
+    /// Whether the value is `null`
+    fn is_null(&self) -> bool;
+    /// Whether the value is `undefined`, which JSON has no way to spell
+    fn is_undefined(&self) -> bool {
+        false
+    }
+    /// Whether the value is an object (or an array)
+    fn is_object(&self) -> bool;
+    /// The field `key` of an object, `None` when it is missing (or `undefined`)
+    fn get(&self, key: &Key) -> Result<Option<Self>>;
+    /// The own string-keyed fields of an object, in enumeration order
+    fn fields(&self) -> Result<Fields<Self>>;
+    /// The value of a BigInt as an i64, `None` when the value is no BigInt
+    fn as_bigint(&self) -> Option<Result<i64>> {
+        None
+    }
+    /// Encode a value nested in another one, e.g. the `$id` of a DBRef
+    fn encode(&self, ctx: &mut Context) -> Result<Bson>;
+}
+
+impl ValueAccess for JsValue {
+    fn as_f64(&self) -> Option<f64> {
+        JsValue::as_f64(self)
+    }
+
+    fn as_string(&self) -> Option<String> {
+        JsValue::as_string(self)
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        JsValue::as_bool(self)
+    }
+
+    fn is_null(&self) -> bool {
+        JsValue::is_null(self)
+    }
+
+    fn is_undefined(&self) -> bool {
+        JsValue::is_undefined(self)
+    }
+
+    fn is_object(&self) -> bool {
+        JsValue::is_object(self)
+    }
+
+    fn get(&self, key: &Key) -> Result<Option<Self>> {
+        let value = keys::get(self, key)?;
+        Ok(if value.is_undefined() {
+            None
+        } else {
+            Some(value)
+        })
+    }
+
+    fn fields(&self) -> Result<Fields<Self>> {
+        object::fields(self)
+    }
+
+    fn as_bigint(&self) -> Option<Result<i64>> {
+        if !self.is_bigint() {
+            return None;
+        }
+        Some(
+            i64::try_from(self.clone()).map_err(|_| EncodeError::InvalidValue {
+                field: "$numberLong",
+                reason: String::from("BigInt is out of range for a 64-bit integer"),
+            }),
+        )
+    }
+
+    fn encode(&self, ctx: &mut Context) -> Result<Bson> {
+        value::encode_value(ctx, self)
+    }
+}
+
+/// JSON values are borrowed, so reading the fields of an object doesn't copy them
+impl ValueAccess for &Value {
+    fn as_f64(&self) -> Option<f64> {
+        Value::as_f64(self)
+    }
+
+    fn as_string(&self) -> Option<String> {
+        self.as_str().map(str::to_owned)
+    }
+
+    fn as_bool(&self) -> Option<bool> {
+        Value::as_bool(self)
+    }
+
+    fn is_null(&self) -> bool {
+        Value::is_null(self)
+    }
+
+    fn is_object(&self) -> bool {
+        Value::is_object(self) || Value::is_array(self)
+    }
+
+    fn get(&self, key: &Key) -> Result<Option<Self>> {
+        Ok(Value::get(self, key.name))
+    }
+
+    fn fields(&self) -> Result<Fields<Self>> {
+        Ok(match self {
+            Value::Object(object) => object.iter().map(|(key, val)| (key.clone(), val)).collect(),
+            _ => vec![],
+        })
+    }
+
+    fn encode(&self, ctx: &mut Context) -> Result<Bson> {
+        super::json::encode_value(ctx, self)
+    }
+}
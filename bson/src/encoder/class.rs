@@ -0,0 +1,94 @@
+use bson::{Bson, Decimal128};
+use wasm_bindgen::{JsCast, JsValue};
+
+use super::{extended, EncodeError, Result};
+use crate::keys;
+
+/// Recognize the wrapper classes of the javascript `bson` library by their
+/// `_bsontype` discriminator, usually defined on the class prototype:
+/// - `Long`: `{low, high, unsigned}`, the two signed 32-bit halves of an `Int64`
+/// - `Int32`, `Double`: `{value}`
+/// - `Decimal128`: `{bytes}`, the 16 bytes of the decimal in BSON byte order
+/// - `ObjectId`, or `ObjectID` up to js-bson 4.x: read through its `toHexString()`
+///   method, since where the bytes are stored differs between library versions
+///   (4.x keeps them under a symbol key)
+///
+/// Other `_bsontype`s are encoded like any other object.
+pub(crate) fn inspect(target: &JsValue) -> Result<Option<Bson>> {
+    let kind = match keys::get(target, &keys::BSON_TYPE)?.as_string() {
+        Some(kind) => kind,
+        None => return Ok(None),
+    };
+    Ok(Some(match kind.as_str() {
+        "Long" => Bson::Int64(long(target)?),
+        "Int32" => Bson::Int32(int32(&keys::get(target, &keys::VALUE)?, "Int32.value")?),
+        "Double" => Bson::Double(keys::get(target, &keys::VALUE)?.as_f64().ok_or(
+            EncodeError::InvalidType {
+                field: "Double.value",
+                expected: "number",
+            },
+        )?),
+        "Decimal128" => Bson::Decimal128(decimal(target)?),
+        "ObjectId" | "ObjectID" => Bson::ObjectId(object_id(target)?),
+        _ => return Ok(None),
+    }))
+}
+
+/// Join the two halves of a `Long`, rejecting unsigned values beyond `i64::MAX`
+fn long(target: &JsValue) -> Result<i64> {
+    let low = int32(&keys::get(target, &keys::LOW)?, "Long.low")?;
+    let high = int32(&keys::get(target, &keys::HIGH)?, "Long.high")?;
+    let unsigned = keys::get(target, &keys::UNSIGNED)?.is_truthy();
+    if unsigned && high < 0 {
+        return Err(EncodeError::InvalidValue {
+            field: "Long",
+            reason: String::from("unsigned value out of range for Int64"),
+        });
+    }
+    Ok(((high as i64) << 32) | (low as u32 as i64))
+}
+
+/// A number holding a signed 32-bit integer
+fn int32(target: &JsValue, field: &'static str) -> Result<i32> {
+    let n = target.as_f64().ok_or(EncodeError::InvalidType {
+        field,
+        expected: "number",
+    })?;
+    if n.fract() != 0.0 || n < i32::MIN as f64 || n > i32::MAX as f64 {
+        return Err(EncodeError::InvalidValue {
+            field,
+            reason: format!("{} is out of range for a 32-bit integer", n),
+        });
+    }
+    Ok(n as i32)
+}
+
+fn decimal(target: &JsValue) -> Result<Decimal128> {
+    let bytes = keys::get(target, &keys::BYTES)?;
+    let bytes = match bytes.dyn_ref::<js_sys::Uint8Array>() {
+        Some(bytes) if bytes.length() == 16 => bytes.to_vec(),
+        _ => {
+            return Err(EncodeError::InvalidType {
+                field: "Decimal128.bytes",
+                expected: "16 bytes",
+            })
+        }
+    };
+    let mut array = [0; 16];
+    array.copy_from_slice(&bytes);
+    Ok(Decimal128::from_bytes(array))
+}
+
+fn object_id(target: &JsValue) -> Result<bson::oid::ObjectId> {
+    let method = keys::get(target, &keys::TO_HEX_STRING)?;
+    let hex = method
+        .dyn_ref::<js_sys::Function>()
+        .map(|method| method.call0(target))
+        .transpose()?
+        .and_then(|hex| hex.as_string())
+        .ok_or(EncodeError::InvalidType {
+            field: "ObjectId",
+            expected: "toHexString() method returning a string",
+        })?;
+    extended::parse_oid(&hex)
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::keys;
+
+/// Failures that can occur while encoding a JsValue into BSON
+#[derive(Debug)]
+pub enum EncodeError {
+    /// A required field of an extended JSON value is missing
+    MissingField(&'static str),
+    /// A field holds a javascript value of the wrong type
+    InvalidType {
+        field: &'static str,
+        expected: &'static str,
+    },
+    /// A field has the right type, but its value can't be converted
+    InvalidValue { field: &'static str, reason: String },
+    /// A base64 payload can't be decoded, `input` is the start of the payload
+    Base64 {
+        field: &'static str,
+        input: String,
+        error: base64::DecodeError,
+    },
+    /// A hex string can't be decoded, `input` is the start of the string
+    Hex {
+        field: &'static str,
+        input: String,
+        error: hex::FromHexError,
+    },
+    /// An object mixes different extended JSON type markers, listed by key
+    ConflictingMarkers(Vec<String>),
+    /// A lone `$`-prefixed key that is no extended JSON type marker, only reported
+    /// with `EncodeOptions::strict_markers`
+    UnknownMarker(String),
+    /// Two entries of a `Map` have the same key, only reported with
+    /// `EncodeOptions::reject_duplicate_keys`
+    DuplicateKey(String),
+    /// The javascript type has no BSON representation
+    Unsupported(String),
+    /// Objects and arrays are nested deeper than the configured maximum depth
+    DepthExceeded(usize),
+    /// An element of a batch can't be encoded, `index` is its position in the batch
+    Element {
+        index: usize,
+        error: Box<EncodeError>,
+    },
+    /// An error located in a nested value, `path` is JSON-path like, e.g.
+    /// `$.users[2].created.$date`
+    At {
+        path: String,
+        error: Box<EncodeError>,
+    },
+    /// The serialized document is larger than the allowed document size, in bytes
+    DocumentTooLarge { size: usize },
+    /// The encoded document can't be serialized
+    Serialize(bson::ser::Error),
+    /// Raw BSON bytes can't be parsed, e.g. because they are truncated
+    Deserialize(bson::de::Error),
+    /// An exception thrown by the javascript runtime, e.g. from a getter
+    Js(JsValue),
+}
+
+impl EncodeError {
+    /// A stable, machine readable name of the failure, e.g. `"invalid_type"`,
+    /// located errors have the code of the error they locate
+    pub fn code(&self) -> &'static str {
+        match self {
+            EncodeError::MissingField(_) => "missing_field",
+            EncodeError::InvalidType { .. } => "invalid_type",
+            EncodeError::InvalidValue { .. } => "invalid_value",
+            EncodeError::Base64 { .. } => "invalid_base64",
+            EncodeError::Hex { .. } => "invalid_hex",
+            EncodeError::ConflictingMarkers(_) => "conflicting_markers",
+            EncodeError::UnknownMarker(_) => "unknown_marker",
+            EncodeError::DuplicateKey(_) => "duplicate_key",
+            EncodeError::Unsupported(_) => "unsupported_type",
+            EncodeError::DepthExceeded(_) => "depth_exceeded",
+            EncodeError::Element { error, .. } | EncodeError::At { error, .. } => error.code(),
+            EncodeError::DocumentTooLarge { .. } => "document_too_large",
+            EncodeError::Serialize(_) => "serialize",
+            EncodeError::Deserialize(_) => "deserialize",
+            EncodeError::Js(_) => "js_exception",
+        }
+    }
+
+    /// The JSON-path like location of the failing value, if known; elements of a
+    /// batch are located by their index, e.g. `$[1].name`
+    pub fn path(&self) -> Option<String> {
+        match self {
+            EncodeError::At { path, .. } => Some(path.clone()),
+            EncodeError::Element { index, error } => {
+                let inner = error.path().unwrap_or_default();
+                Some(format!("$[{}]{}", index, inner.trim_start_matches('$')))
+            }
+            _ => None,
+        }
+    }
+
+    /// The error itself, without the location added by `At` and `Element`
+    pub fn cause(&self) -> &EncodeError {
+        match self {
+            EncodeError::Element { error, .. } | EncodeError::At { error, .. } => error.cause(),
+            error => error,
+        }
+    }
+
+    /// A javascript `{code, message, path}` object describing the error, `path`
+    /// being `null` when unknown
+    pub fn to_js_object(&self) -> JsValue {
+        error_object(self.code(), &self.cause().to_string(), self.path())
+    }
+
+    /// Root the path of a located error at the top level value, `.a[0]` becomes `$.a[0]`
+    pub(crate) fn rooted(self) -> Self {
+        match self {
+            EncodeError::At { path, error } => EncodeError::At {
+                path: format!("${}", path),
+                error,
+            },
+            error => error,
+        }
+    }
+
+    /// A base64 error of `field`, keeping the start of the offending input
+    pub(crate) fn base64(field: &'static str, input: &str, error: base64::DecodeError) -> Self {
+        EncodeError::Base64 {
+            field,
+            input: preview(input),
+            error,
+        }
+    }
+
+    /// A hex error of `field`, keeping the start of the offending input
+    pub(crate) fn hex(field: &'static str, input: &str, error: hex::FromHexError) -> Self {
+        EncodeError::Hex {
+            field,
+            input: preview(input),
+            error,
+        }
+    }
+
+    /// Whether this is, or locates, an exception thrown by the javascript runtime
+    pub(crate) fn is_js(&self) -> bool {
+        match self {
+            EncodeError::Js(_) => true,
+            EncodeError::At { error, .. } => error.is_js(),
+            _ => false,
+        }
+    }
+}
+
+/// The first 32 characters of an input, marked with `...` when it's longer
+fn preview(input: &str) -> String {
+    const LENGTH: usize = 32;
+    match input.char_indices().nth(LENGTH) {
+        Some((end, _)) => format!("{}...", &input[..end]),
+        None => input.to_owned(),
+    }
+}
+
+/// Build a javascript `{code, message, path}` error object
+pub(crate) fn error_object(code: &str, message: &str, path: Option<String>) -> JsValue {
+    let object = js_sys::Object::new();
+    let path = path.map_or(JsValue::NULL, |path| JsValue::from_str(&path));
+    // setting plain data properties on a fresh object can't throw
+    let _ = keys::set(&object, &keys::ERROR_CODE, &code.into());
+    let _ = keys::set(&object, &keys::ERROR_MESSAGE, &message.into());
+    let _ = keys::set(&object, &keys::ERROR_PATH, &path);
+    object.into()
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::MissingField(field) => write!(f, "missing {}", field),
+            EncodeError::InvalidType { field, expected } => {
+                write!(f, "invalid {}: expected {}", field, expected)
+            }
+            EncodeError::InvalidValue { field, reason } => {
+                write!(f, "invalid {}: {}", field, reason)
+            }
+            EncodeError::Base64 {
+                field,
+                input,
+                error,
+            } => write!(f, "invalid base64 in {} {:?}: {}", field, input, error),
+            EncodeError::Hex {
+                field,
+                input,
+                error,
+            } => write!(f, "invalid hex in {} {:?}: {}", field, input, error),
+            EncodeError::ConflictingMarkers(keys) => write!(
+                f,
+                "conflicting extended JSON type markers: {}",
+                keys.join(", ")
+            ),
+            EncodeError::UnknownMarker(key) => write!(f, "unknown extended JSON type: {}", key),
+            EncodeError::DuplicateKey(key) => write!(f, "duplicate key {:?}", key),
+            EncodeError::Serialize(err) => write!(f, "error writing document: {}", err),
+            EncodeError::Deserialize(err) => write!(f, "error parsing document: {}", err),
+            EncodeError::Unsupported(kind) => write!(f, "type not valid in BSON spec: {}", kind),
+            EncodeError::DepthExceeded(max) => {
+                write!(
+                    f,
+                    "value is nested deeper than the maximum depth of {}",
+                    max
+                )
+            }
+            EncodeError::Element { index, error } => write!(f, "element {}: {}", index, error),
+            EncodeError::At { path, error } => write!(f, "at {}: {}", path, error),
+            EncodeError::DocumentTooLarge { size } => write!(
+                f,
+                "document of {} bytes exceeds the maximum document size",
+                size
+            ),
+            EncodeError::Js(err) => match err.dyn_ref::<js_sys::Error>() {
+                Some(err) => write!(f, "{}", String::from(err.message())),
+                None => write!(f, "{:?}", err),
+            },
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EncodeError::Base64 { error, .. } => Some(error),
+            EncodeError::Hex { error, .. } => Some(error),
+            EncodeError::Serialize(err) => Some(err),
+            EncodeError::Deserialize(err) => Some(err),
+            EncodeError::Element { error, .. } | EncodeError::At { error, .. } => {
+                Some(error.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<bson::ser::Error> for EncodeError {
+    fn from(err: bson::ser::Error) -> Self {
+        EncodeError::Serialize(err)
+    }
+}
+
+impl From<bson::de::Error> for EncodeError {
+    fn from(err: bson::de::Error) -> Self {
+        EncodeError::Deserialize(err)
+    }
+}
+
+impl From<JsValue> for EncodeError {
+    fn from(err: JsValue) -> Self {
+        EncodeError::Js(err)
+    }
+}
+
+impl From<EncodeError> for JsValue {
+    fn from(err: EncodeError) -> Self {
+        match err {
+            // rethrow javascript exceptions untouched, even from nested values
+            EncodeError::Js(err) => err,
+            EncodeError::At { error, .. } if error.is_js() => (*error).into(),
+            err => js_sys::Error::new(&err.to_string()).into(),
+        }
+    }
+}
@@ -1,124 +1,622 @@
-use bson::{oid::ObjectId, Bson};
+use std::convert::TryFrom;
+
+use bson::{oid::ObjectId, spec::BinarySubtype, Bson, Decimal128, Document};
 use chrono::prelude::*;
-use wasm_bindgen::JsValue;
 
-use super::number;
-use crate::Result;
+use super::object::{field, Fields};
+use super::{number, Context, EncodeError, Result, ValueAccess};
+use crate::keys;
 
 /// `{“$oid”: ”<oid>”}`
 /// <oid>: A 24-character, big-endian hexadecimal string that represents the ObjectId bytes.
-fn oid(target: &JsValue) -> Result<Bson> {
-    let oid = target
-        .as_string()
-        .ok_or_else(|| "failed to extract object id value")?;
-    Ok(Bson::ObjectId(ObjectId::with_string(&oid).map_err(
-        |err| format!("error in ObjectID value: {}", err.to_string()),
-    )?))
+pub(crate) fn oid(target: &impl ValueAccess) -> Result<Bson> {
+    let oid = target.as_string().ok_or(EncodeError::InvalidType {
+        field: "$oid",
+        expected: "string",
+    })?;
+    Ok(Bson::ObjectId(parse_oid(&oid)?))
+}
+
+/// Parse the hex string of an `$oid`
+pub(crate) fn parse_oid(oid: &str) -> Result<ObjectId> {
+    if oid.len() != 24 || !oid.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(EncodeError::InvalidType {
+            field: "$oid",
+            expected: "24-char hex string",
+        });
+    }
+    ObjectId::parse_str(oid).map_err(|err| EncodeError::InvalidValue {
+        field: "$oid",
+        reason: err.to_string(),
+    })
 }
 
-/// `{"$date": {"$numberLong": "<millis>"}}`
+/// `{"$date": {"$numberLong": "<millis>"}}` or, in relaxed mode, `{"$date": "<ISO-8601>"}`
 /// <millis>: A 64-bit signed integer as string. The value represents milliseconds relative to the epoch.
-fn date(target: &JsValue) -> Result<Bson> {
-    let ms = js_sys::Reflect::get(target, &JsValue::from_str("$numberLong"))?;
-    let ms = number::long(&ms)?;
-    let secs = ms / 1e3 as i64; // [s]
-    let nsecs = ((ms % 1e3 as i64) * 1e6 as i64) as u32; // [ns]
-    let date = chrono::Utc.timestamp(secs, nsecs);
-    Ok(Bson::DateTime(date))
+/// Older drivers write <millis> as a plain number (`{"$numberLong": 1565545664000}`), which
+/// `number::long` accepts as long as it is a safe integer.
+/// <ISO-8601>: An ISO-8601 Internet Date/Time Format as described in RFC-3339, with millisecond precision.
+fn date(ctx: &mut Context, target: &impl ValueAccess) -> Result<Bson> {
+    if let Some(iso) = target.as_string() {
+        return Ok(Bson::DateTime(parse_iso_date(&iso)?));
+    }
+    if !target.is_object() {
+        // e.g. a bare number, which could be seconds as well as millis
+        return Err(DATE_SHAPE);
+    }
+
+    // the millis are themselves extended JSON, decode them like any other value
+    let ms = match target.encode(ctx)? {
+        Bson::Int64(ms) => ms,
+        _ => return Err(DATE_SHAPE),
+    };
+    // any i64 is a valid BSON date, even far outside the years chrono can represent
+    Ok(Bson::DateTime(bson::DateTime::from_millis(ms)))
+}
+
+/// A `$date` that is neither of its two forms
+pub(crate) const DATE_SHAPE: EncodeError = EncodeError::InvalidType {
+    field: "$date",
+    expected: "a string or {\"$numberLong\": \"<millis>\"}",
+};
+
+/// Parse the relaxed `<ISO-8601>` form of a `$date`
+pub(crate) fn parse_iso_date(iso: &str) -> Result<bson::DateTime> {
+    let date = chrono::DateTime::parse_from_rfc3339(iso)
+        .map(|date| date.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(iso, "%Y-%m-%dT%H:%M:%S%.fZ")
+                .map(|date| Utc.from_utc_datetime(&date))
+        })
+        .map_err(|err| EncodeError::InvalidValue {
+            field: "$date",
+            reason: err.to_string(),
+        })?;
+    Ok(date.into())
 }
 
 /// {"$timestamp": {"t": <t>, "i": <i>}}
 /// <t>: A positive integer for the seconds since epoch.
 /// <i>: A positive integer for the increment.
-fn timestamp(target: &JsValue) -> Result<Bson> {
-    let t = js_sys::Reflect::get(target, &JsValue::from_str("t"))?;
-    let i = js_sys::Reflect::get(target, &JsValue::from_str("i"))?;
-    let t = t.as_f64().ok_or_else(|| "invalid t in $timestamp")?;
-    let i = i.as_f64().ok_or_else(|| "invalid i in $timestamp")?;
-    let time = (t / 1e3) as u32; // [s]
-    let increment = i as u32;
+/// Both may also be given as numeric strings. Neither is scaled, and values beyond
+/// `u32::MAX` are rejected instead of being truncated.
+pub(crate) fn timestamp<V: ValueAccess>(target: &V) -> Result<Bson> {
+    let uint = |value: Option<V>, field| match value {
+        Some(value) => number::uint(&value, field),
+        None => Err(EncodeError::InvalidType {
+            field,
+            expected: "number or numeric string",
+        }),
+    };
+    let time = uint(target.get(&keys::T)?, "$timestamp.t")?; // [s]
+    let increment = uint(target.get(&keys::I)?, "$timestamp.i")?;
     Ok(Bson::Timestamp(bson::Timestamp { time, increment }))
 }
 
 /// { "$regularExpression": { "pattern": "<regexPattern>", "options": "<options>" } }
 /// <regexPattern>: A string that corresponds to the regular expression pattern.
 ///     The string can contain valid JSON characters and unescaped double quote (") characters,
-///     but may not contain unescaped forward slash (/) characters.
+///     but may not contain unescaped forward slash (/) characters, which couldn't be written
+///     back as a legacy `/pattern/flags` literal; see `EncodeOptions::lenient_regex`.
 /// <options>: A string that specifies BSON regular expression options (‘g’, ‘i’, ‘m’ and ‘s’) or an empty string "".
 ///     Options other than (‘g’, ‘i’, ‘m’ and ‘s’) will be dropped when converting to this representation.
 ///     !! The options MUST be in alphabetical order.
-fn regex(target: &JsValue) -> Result<Bson> {
-    let pattern = js_sys::Reflect::get(target, &JsValue::from_str("pattern"))?;
-    let options = js_sys::Reflect::get(target, &JsValue::from_str("options"))?;
-    let pattern = pattern
-        .as_string()
-        .ok_or_else(|| "invalid pattern in $regularExpression")?;
-    let options = options
-        .as_string()
-        .ok_or_else(|| "invalid options in $regularExpression")?;
-
-    // sort options...
-    let mut chars = options.chars().collect::<Vec<char>>();
-    chars.sort_by(|a, b| a.cmp(b));
-    let s = chars.into_iter().collect::<String>();
-    let options = String::from(s.trim());
+///     Relaxed inputs may omit `options` (or set it to null), which means no options;
+///     whitespace and unordered flags are normalized by `regex_options`.
+pub(crate) fn regex(ctx: &mut Context, target: &impl ValueAccess) -> Result<Bson> {
+    let pattern = target.get(&keys::PATTERN)?;
+    let options = target.get(&keys::OPTIONS)?;
+    let pattern =
+        pattern
+            .and_then(|pattern| pattern.as_string())
+            .ok_or(EncodeError::InvalidType {
+                field: "$regularExpression.pattern",
+                expected: "string",
+            })?;
+    if let Some(index) = unescaped_slash(&pattern) {
+        if !ctx.options.lenient_regex {
+            return Err(EncodeError::InvalidValue {
+                field: "$regularExpression.pattern",
+                reason: format!("unescaped '/' at index {}, escape it as '\\/'", index),
+            });
+        }
+        ctx.warn(|| format!("unescaped '/' at index {} of regex pattern", index));
+    }
+    let options = match options {
+        Some(options) if !options.is_null() => options,
+        _ => {
+            return Ok(Bson::RegularExpression(bson::Regex {
+                pattern,
+                options: String::new(),
+            }))
+        }
+    };
+    let options = options.as_string().ok_or(EncodeError::InvalidType {
+        field: "$regularExpression.options",
+        expected: "string",
+    })?;
+
+    let options = regex_options(ctx, &options);
 
     Ok(Bson::RegularExpression(bson::Regex { pattern, options }))
 }
 
+/// The byte index of the first `/` of a pattern that isn't escaped by a backslash
+fn unescaped_slash(pattern: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (index, c) in pattern.char_indices() {
+        match c {
+            '/' if !escaped => return Some(index),
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Normalize regular expression options, keeping only the allowed flags
+/// (‘g’, ‘i’, ‘m’ and ‘s’) without duplicates and in alphabetical order.
+/// Anything else, including whitespace anywhere in the string, is dropped,
+/// e.g. `" i g "` becomes `"gi"`. Dropped flags other than whitespace are warned about.
+pub(crate) fn regex_options(ctx: &mut Context, options: &str) -> String {
+    const FLAGS: [char; 4] = ['g', 'i', 'm', 's'];
+    let mut present = [false; 4];
+    for c in options.chars() {
+        match FLAGS.iter().position(|flag| *flag == c) {
+            Some(i) => present[i] = true,
+            None if c.is_whitespace() => {}
+            None => ctx.warn(|| format!("dropped regex flag '{}'", c)),
+        }
+    }
+    FLAGS
+        .iter()
+        .zip(present.iter())
+        .filter(|(_, present)| **present)
+        .map(|(flag, _)| flag)
+        .collect()
+}
+
+/// Binary subtypes that may be spelled by name instead of hex in `subType`
+const SUBTYPE_NAMES: [&str; 8] = [
+    "generic",
+    "function",
+    "binaryOld",
+    "uuidOld",
+    "uuid",
+    "md5",
+    "encrypted",
+    "userDefined",
+];
+
+/// Map a subtype name (case insensitive) to its BinarySubtype
+fn subtype_name(name: &str) -> Option<BinarySubtype> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "generic" => BinarySubtype::Generic,
+        "function" => BinarySubtype::Function,
+        "binaryold" => BinarySubtype::BinaryOld,
+        "uuidold" => BinarySubtype::UuidOld,
+        "uuid" => BinarySubtype::Uuid,
+        "md5" => BinarySubtype::Md5,
+        "encrypted" => BinarySubtype::Encrypted,
+        "userdefined" => BinarySubtype::UserDefined(0x80),
+        _ => return None,
+    })
+}
+
 /// {"$binary": {"base64": <payload>, "subType": <t>}}
-/// <payload>: Base64 encoded (with padding as “=”) payload string.
-/// <t>: A one- or two-character hex string that corresponds to a BSON binary subtype.
-fn binary(target: &JsValue) -> Result<Bson> {
-    let bytes = js_sys::Reflect::get(target, &JsValue::from_str("base64"))?;
-    let subtype = js_sys::Reflect::get(target, &JsValue::from_str("subType"))?;
+/// <payload>: Base64 encoded (with padding as “=”) payload string, URL-safe base64 is accepted too.
+/// <t>: A one- or two-character hex string that corresponds to a BSON binary subtype,
+///     or one of the subtype names in `SUBTYPE_NAMES` (e.g. "uuid"). Defaults to generic when omitted.
+pub(crate) fn binary(target: &impl ValueAccess) -> Result<Bson> {
+    let bytes = target.get(&keys::BASE64)?;
+    let subtype = target.get(&keys::SUB_TYPE)?;
     let bytes = bytes
-        .as_string()
-        .ok_or_else(|| "invalid base64 in $binary")?;
-    let subtype = subtype
-        .as_string()
-        .ok_or_else(|| "invalid subType in $binary")?;
+        .and_then(|bytes| bytes.as_string())
+        .ok_or(EncodeError::InvalidType {
+            field: "$binary.base64",
+            expected: "string",
+        })?;
+    let subtype = match subtype {
+        Some(subtype) if !subtype.is_null() => {
+            Some(subtype.as_string().ok_or(EncodeError::InvalidType {
+                field: "$binary.subType",
+                expected: "string",
+            })?)
+        }
+        _ => None,
+    };
+    Ok(Bson::Binary(parse_binary(&bytes, subtype)?))
+}
+
+/// Decode the base64 payload and the hex or named subtype of a `$binary`,
+/// a missing subtype (as relaxed inputs may omit it) means generic binary
+pub(crate) fn parse_binary(bytes: &str, subtype: Option<String>) -> Result<bson::Binary> {
+    // the standard alphabet, or the URL-safe one (`-` and `_`) some pipelines emit
     let bytes = base64::decode(bytes)
-        .map_err(|err| format!("invalid base64 in $binary: {}", err.to_string()))?;
-    let subtype = hex::decode(subtype)
-        .map_err(|err| format!("invalid subType in $binary: {}", err.to_string()))?;
+        .or_else(|err| base64::decode_config(bytes, base64::URL_SAFE).map_err(|_| err))
+        .map_err(|err| EncodeError::base64("$binary.base64", bytes, err))?;
+    let subtype = match subtype {
+        Some(subtype) => subtype,
+        None => {
+            return Ok(bson::Binary {
+                bytes,
+                subtype: BinarySubtype::Generic,
+            })
+        }
+    };
+    if let Some(subtype) = subtype_name(&subtype) {
+        return Ok(bson::Binary { bytes, subtype });
+    }
+    if subtype.len() > 2 {
+        return Err(EncodeError::InvalidValue {
+            field: "$binary.subType",
+            reason: format!(
+                "unknown subtype {:?}, expected a hex byte or one of {}",
+                subtype,
+                SUBTYPE_NAMES.join(", ")
+            ),
+        });
+    }
+    // a one-character subtype is zero-padded, e.g. "5" is the same as "05"
+    let subtype = if subtype.len() == 1 {
+        format!("0{}", subtype)
+    } else {
+        subtype
+    };
+    let subtype =
+        hex::decode(&subtype).map_err(|err| EncodeError::hex("$binary.subType", &subtype, err))?;
 
-    if subtype.len() == 1 {
-        Ok(Bson::Binary(bson::Binary {
+    match subtype.as_slice() {
+        [subtype] => Ok(bson::Binary {
             bytes,
-            subtype: subtype[0].into(),
-        }))
-    } else {
-        Err("invalid subType in $binary".into())
+            subtype: (*subtype).into(),
+        }),
+        _ => Err(EncodeError::InvalidValue {
+            field: "$binary.subType",
+            reason: String::from("expected a single byte"),
+        }),
     }
 }
 
-/// Inspect an extended JSON JsValue
-/// For reference: https://docs.mongodb.com/manual/reference/mongodb-extended-json/
-pub fn inspect(target: &JsValue) -> Result<Option<Bson>> {
-    // extended JSON check (`$`)
-    let keys = js_sys::Reflect::own_keys(target)?;
-    let keys = keys.to_vec();
-    match keys.get(0) {
-        Some(key) => {
-            let val = js_sys::Reflect::get(target, &key)?;
-            let key = key
-                .as_string()
-                .ok_or_else(|| "failed to extract object key")?;
-            Ok(match key.as_str() {
-                "$oid" => Some(oid(&val)?),
-                "$date" => Some(date(&val)?),
-                "$numberDouble" => Some(Bson::Double(number::double(&val)?)),
-                "$numberInt" => Some(Bson::Int32(number::int(&val)?)),
-                "$numberLong" => Some(Bson::Int64(number::long(&val)?)),
-                "$minKey" => Some(Bson::MinKey),
-                "$maxKey" => Some(Bson::MaxKey),
-                "$regularExpression" => Some(regex(&val)?),
-                "$timestamp" => Some(timestamp(&val)?),
-                "$binary" => Some(binary(&val)?),
-                _ => None,
+/// `{"$uuid": "<uuid>"}`
+/// <uuid>: A 36-character hyphenated hex string (e.g. "c8edabc3-f738-4ca3-b68d-ab92a91478a3").
+///     Shorthand for a `$binary` with subtype 4 (UUID).
+pub(crate) fn uuid(target: &impl ValueAccess) -> Result<Bson> {
+    let uuid = target.as_string().ok_or(EncodeError::InvalidType {
+        field: "$uuid",
+        expected: "string",
+    })?;
+    Ok(Bson::Binary(parse_uuid(&uuid)?))
+}
+
+/// Parse the hyphenated hex string of a `$uuid`, only the canonical 8-4-4-4-12
+/// layout is accepted: no bare 32-digit form and no hyphens anywhere else
+pub(crate) fn parse_uuid(uuid: &str) -> Result<bson::Binary> {
+    let canonical = uuid.len() == 36
+        && uuid
+            .char_indices()
+            .all(|(i, c)| (c == '-') == matches!(i, 8 | 13 | 18 | 23));
+    if !canonical {
+        return Err(EncodeError::InvalidValue {
+            field: "$uuid",
+            reason: String::from("expected 36 characters in the 8-4-4-4-12 hyphenated form"),
+        });
+    }
+    // 32 hex digits once the hyphens are removed, so 16 bytes
+    let bytes =
+        hex::decode(uuid.replace('-', "")).map_err(|err| EncodeError::hex("$uuid", uuid, err))?;
+    Ok(bson::Binary {
+        bytes,
+        subtype: BinarySubtype::Uuid,
+    })
+}
+
+/// `{"$code": "<javascript>"}` or `{"$code": "<javascript>", "$scope": {...}}`
+/// <javascript>: A string that corresponds to the JavaScript code.
+/// When a sibling `$scope` object is present, it is encoded as a document and
+/// the value becomes a JavaScript code with scope.
+/// !! Unlike other markers, this reads both keys off the wrapper object fields.
+fn code<V: ValueAccess>(ctx: &mut Context, fields: &Fields<V>) -> Result<Bson> {
+    let code = ctx.at(
+        || String::from(".$code"),
+        |_| {
+            field(fields, "$code")
+                .and_then(V::as_string)
+                .ok_or(EncodeError::InvalidType {
+                    field: "$code",
+                    expected: "string",
+                })
+        },
+    )?;
+    let scope = match field(fields, "$scope") {
+        Some(scope) => scope,
+        None => return Ok(Bson::JavaScriptCode(code)),
+    };
+    // the scope is encoded like any other value, sharing the depth budget and
+    // path of the enclosing value, so nested scopes can't recurse unbounded
+    let scope = ctx.at(
+        || String::from(".$scope"),
+        |ctx| match scope.encode(ctx)? {
+            Bson::Document(scope) => Ok(scope),
+            _ => Err(EncodeError::InvalidType {
+                field: "$scope",
+                expected: "document",
+            }),
+        },
+    )?;
+    Ok(Bson::JavaScriptCodeWithScope(
+        bson::JavaScriptCodeWithScope { code, scope },
+    ))
+}
+
+/// `{"$symbol": "<string>"}`
+/// <string>: The symbol value. Symbols are deprecated, but still found in legacy data.
+pub(crate) fn symbol(target: &impl ValueAccess) -> Result<Bson> {
+    let symbol = target.as_string().ok_or(EncodeError::InvalidType {
+        field: "$symbol",
+        expected: "string",
+    })?;
+    Ok(Bson::Symbol(symbol))
+}
+
+/// `{"$undefined": true}`
+/// The value MUST be `true`. Undefined is deprecated, but still found in legacy dumps.
+pub(crate) fn undefined(target: &impl ValueAccess) -> Result<Bson> {
+    match target.as_bool() {
+        Some(true) => Ok(Bson::Undefined),
+        _ => Err(EncodeError::InvalidType {
+            field: "$undefined",
+            expected: "true",
+        }),
+    }
+}
+
+/// `{"$minKey": 1}` or `{"$maxKey": 1}`
+/// The value MUST be the integer 1, anything else (e.g. `true`) is rejected.
+pub(crate) fn key(target: &impl ValueAccess, field: &'static str, key: Bson) -> Result<Bson> {
+    match target.as_f64() {
+        Some(1.0) => Ok(key),
+        _ => Err(EncodeError::InvalidType {
+            field,
+            expected: "the integer 1",
+        }),
+    }
+}
+
+/// `{"$ref": "<collection>", "$id": <id>, "$db": "<database>"}`
+/// <collection>: The name of the referenced collection.
+/// <id>: The `_id` of the referenced document, encoded like any other value.
+/// <database>: Optional, the name of the database of the referenced collection.
+/// Fields are emitted in canonical `$ref`, `$id`, `$db` order, followed by any extra fields.
+fn dbref<V: ValueAccess>(ctx: &mut Context, fields: &Fields<V>) -> Result<Bson> {
+    let reference =
+        field(fields, "$ref")
+            .and_then(V::as_string)
+            .ok_or(EncodeError::InvalidType {
+                field: "$ref",
+                expected: "string",
+            })?;
+    let id = field(fields, "$id").ok_or(EncodeError::MissingField("$id"))?;
+
+    let mut document = Document::new();
+    document.insert("$ref", reference);
+    document.insert("$id", id.encode(ctx)?);
+    if let Some(db) = field(fields, "$db") {
+        let db = db.as_string().ok_or(EncodeError::InvalidType {
+            field: "$db",
+            expected: "string",
+        })?;
+        document.insert("$db", db);
+    }
+
+    for (key, val) in fields {
+        if !document.contains_key(key) && !ctx.skips(val) {
+            document.insert(key, val.encode(ctx)?);
+        }
+    }
+    Ok(Bson::Document(document))
+}
+
+/// `{"$dbPointer": {"$ref": "<namespace>", "$id": {"$oid": "<oid>"}}}`
+/// <namespace>: The `db.collection` the pointer refers to.
+/// <oid>: The ObjectId of the referenced document, any other `$id` is rejected.
+/// DBPointer is deprecated, but still found in very old data.
+fn db_pointer(ctx: &mut Context, target: &impl ValueAccess) -> Result<Bson> {
+    let namespace = target
+        .get(&keys::REF)?
+        .ok_or(EncodeError::MissingField("$dbPointer.$ref"))?;
+    let id = target
+        .get(&keys::ID)?
+        .ok_or(EncodeError::MissingField("$dbPointer.$id"))?;
+    let namespace = namespace.as_string().ok_or(EncodeError::InvalidType {
+        field: "$dbPointer.$ref",
+        expected: "string",
+    })?;
+    let id = match id.encode(ctx)? {
+        Bson::ObjectId(id) => id,
+        _ => {
+            return Err(EncodeError::InvalidType {
+                field: "$dbPointer.$id",
+                expected: "{\"$oid\": \"<oid>\"}",
             })
         }
-        None => Ok(None),
+    };
+    // bson only builds DbPointer values out of their extended JSON form
+    let pointer = serde_json::json!({
+        "$dbPointer": { "$ref": namespace, "$id": { "$oid": id.to_hex() } }
+    });
+    Bson::try_from(pointer).map_err(|err| EncodeError::InvalidValue {
+        field: "$dbPointer",
+        reason: err.to_string(),
+    })
+}
+
+/// `{"$numberDecimal": "<number>"}`
+/// <number>: A string representation of a 128-bit decimal, e.g. "10.99", "1E+3", "NaN", "Infinity" or "-0".
+pub(crate) fn decimal(target: &impl ValueAccess) -> Result<Bson> {
+    let decimal = target.as_string().ok_or(EncodeError::InvalidType {
+        field: "$numberDecimal",
+        expected: "string",
+    })?;
+    Ok(Bson::Decimal128(parse_decimal(&decimal)?))
+}
+
+/// Parse the string of a `$numberDecimal`
+pub(crate) fn parse_decimal(decimal: &str) -> Result<Decimal128> {
+    decimal
+        .parse::<Decimal128>()
+        .map_err(|err| EncodeError::InvalidValue {
+            field: "$numberDecimal",
+            reason: err.to_string(),
+        })
+}
+
+/// Every key the encoder recognizes as an extended JSON type marker, including the
+/// keys that only appear next to another one (`$scope`, `$id`, `$db`)
+pub const EXTENDED_JSON_MARKERS: &[&str] = &[
+    "$oid",
+    "$date",
+    "$numberDouble",
+    "$numberInt",
+    "$numberLong",
+    "$numberDecimal",
+    "$minKey",
+    "$maxKey",
+    "$regularExpression",
+    "$timestamp",
+    "$binary",
+    "$uuid",
+    "$code",
+    "$scope",
+    "$symbol",
+    "$undefined",
+    "$dbPointer",
+    "$ref",
+    "$id",
+    "$db",
+];
+
+/// Map an object key to the extended JSON type marker it belongs to.
+/// Keys that are read together (`$code`/`$scope`, `$ref`/`$id`/`$db`) share a marker.
+fn marker(key: &str) -> Option<&'static str> {
+    if !key.starts_with('$') {
+        return None;
+    }
+    let marker = EXTENDED_JSON_MARKERS
+        .iter()
+        .find(|marker| **marker == key)?;
+    Some(match *marker {
+        "$scope" => "$code",
+        "$id" | "$db" => "$ref",
+        marker => marker,
+    })
+}
+
+/// Whether `key` is one of `EXTENDED_JSON_MARKERS`
+pub fn is_extended_json_marker(key: &str) -> bool {
+    marker(key).is_some()
+}
+
+/// Find the extended JSON type marker among the keys of an object, along with the
+/// value of its key. Shared by every encoder so they agree on what is a wrapper:
+/// - no marker, or a marker next to regular keys (only a DBRef may carry extra
+///   fields), e.g. `{"$oid": "abc", "name": "x"}`, means a plain document
+/// - `$scope` without `$code` is a regular key, and so are `$ref`, `$id` and `$db`
+///   unless both `$ref` and `$id` are present, e.g. `{"$id": 1}` or a JSON Schema
+///   style `{"$ref": "#/definitions/a"}` is a plain document
+/// - different markers in the same object, e.g. `{"$oid": "...", "$date": {...}}`,
+///   are rejected
+fn find_marker<'a, V: 'a>(
+    fields: impl IntoIterator<Item = (&'a String, &'a V)>,
+) -> Result<Option<(&'static str, &'a V)>> {
+    let fields: Vec<(&String, &V)> = fields.into_iter().collect();
+    let has = |lead: &str| fields.iter().any(|(key, _)| *key == lead);
+    let dbref = has("$ref") && has("$id");
+
+    // extended JSON check (`$`), the marker may be any of the keys
+    let mut found: Vec<(&'static str, &String, &V)> = vec![];
+    let mut plain = false;
+    for (key, val) in fields.iter().copied() {
+        match marker(key) {
+            // DBRef keys need both `$ref` and `$id`, a companion key only belongs
+            // to a wrapper next to its lead key
+            Some("$ref") if !dbref => plain = true,
+            Some(kind) if kind != key && !has(kind) => plain = true,
+            Some(kind) => {
+                if !found.iter().any(|(other, _, _)| *other == kind) {
+                    found.push((kind, key, val));
+                }
+            }
+            None => plain = true,
+        }
     }
+
+    if plain && !dbref {
+        return Ok(None);
+    }
+
+    match found.as_slice() {
+        [] => Ok(None),
+        [(kind, _, val)] => Ok(Some((*kind, *val))),
+        conflicts => Err(EncodeError::ConflictingMarkers(
+            conflicts.iter().map(|(_, key, _)| (*key).clone()).collect(),
+        )),
+    }
+}
+
+/// Inspect the already extracted fields of an object for extended JSON
+/// For reference: https://docs.mongodb.com/manual/reference/mongodb-extended-json/
+pub(crate) fn inspect<V: ValueAccess>(
+    ctx: &mut Context,
+    fields: &Fields<V>,
+) -> Result<Option<Bson>> {
+    let (kind, val) = match find_marker(fields.iter().map(|(key, val)| (key, val)))? {
+        Some(found) => found,
+        None => match fields.as_slice() {
+            [(key, _)]
+                if ctx.options.strict_markers
+                    && key.starts_with('$')
+                    && !is_extended_json_marker(key) =>
+            {
+                return Err(EncodeError::UnknownMarker(key.clone()))
+            }
+            _ => return Ok(None),
+        },
+    };
+    if kind == "$code" {
+        // `$code` and `$scope` are sibling keys, `code` locates each of them
+        return code(ctx, fields).map(Some);
+    }
+    ctx.at(
+        || format!(".{}", kind),
+        |ctx| marker_value(ctx, kind, val, fields),
+    )
+}
+
+/// Encode the value of an extended JSON wrapper, whose marker is `kind`
+fn marker_value<V: ValueAccess>(
+    ctx: &mut Context,
+    kind: &str,
+    val: &V,
+    fields: &Fields<V>,
+) -> Result<Option<Bson>> {
+    Ok(match kind {
+        "$oid" => Some(oid(val)?),
+        "$date" => Some(date(ctx, val)?),
+        "$numberDouble" => Some(number::explicit_double(ctx, number::double(val)?)),
+        "$numberInt" => Some(Bson::Int32(number::int(val)?)),
+        "$numberLong" => Some(Bson::Int64(number::long(val)?)),
+        "$numberDecimal" => Some(decimal(val)?),
+        "$minKey" => Some(key(val, "$minKey", Bson::MinKey)?),
+        "$maxKey" => Some(key(val, "$maxKey", Bson::MaxKey)?),
+        "$regularExpression" => Some(regex(ctx, val)?),
+        "$timestamp" => Some(timestamp(val)?),
+        "$binary" => Some(binary(val)?),
+        "$uuid" => Some(uuid(val)?),
+        "$symbol" => Some(symbol(val)?),
+        "$undefined" => Some(undefined(val)?),
+        "$dbPointer" => Some(db_pointer(ctx, val)?),
+        "$ref" => Some(dbref(ctx, fields)?),
+        _ => None,
+    })
 }
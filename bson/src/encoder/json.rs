@@ -0,0 +1,40 @@
+use bson::Bson;
+use serde_json::Value;
+
+use super::{number, object, Context, EncodeError, EncodeOptions, Result, ValueAccess};
+
+/// Encode a `serde_json::Value` into a BSON value, interpreting extended JSON type
+/// markers exactly like `encode` does for a JsValue, so the same semantics are
+/// available outside of a javascript runtime.
+///
+/// JSON numbers behave like JS numbers: a plain number is an `Int32`, `Int64` or
+/// `Double` depending on its value, and a number given as `$numberLong` must be a
+/// safe integer.
+pub fn encode_json(value: &Value) -> Result<Bson> {
+    encode_json_with(value, &EncodeOptions::default())
+}
+
+/// Encode a `serde_json::Value` into a BSON value, see `EncodeOptions` for what can be
+/// tuned; options that only concern javascript values (e.g. `undefined`) have no effect
+pub fn encode_json_with(value: &Value, options: &EncodeOptions) -> Result<Bson> {
+    encode_value(&mut Context::new(options), value).map_err(EncodeError::rooted)
+}
+
+/// Encode a generic JSON value, the counterpart of `value::encode_value`
+pub(crate) fn encode_value(ctx: &mut Context, value: &Value) -> Result<Bson> {
+    Ok(match value {
+        Value::Null => Bson::Null,
+        Value::Bool(b) => Bson::Boolean(*b),
+        Value::Number(n) => number::infer(ctx, n.as_f64().unwrap_or(f64::NAN)),
+        Value::String(s) => Bson::String(s.clone()),
+        Value::Array(array) => ctx.nested(|ctx| {
+            array
+                .iter()
+                .enumerate()
+                .map(|(i, x)| ctx.at(|| format!("[{}]", i), |ctx| encode_value(ctx, x)))
+                .collect::<Result<Vec<_>>>()
+                .map(Bson::Array)
+        })?,
+        Value::Object(_) => ctx.nested(|ctx| object::encode_fields(ctx, value.fields()?))?,
+    })
+}
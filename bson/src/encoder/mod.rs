@@ -1,6 +1,120 @@
+use std::io::Write;
+
+use bson::Bson;
+use serde::de::DeserializeOwned;
+use wasm_bindgen::JsValue;
+
+mod access;
+mod class;
+mod error;
 mod extended;
+mod json;
 mod number;
 mod object;
+mod options;
 mod value;
 
+pub(crate) use error::error_object;
+pub use error::EncodeError;
+pub(crate) use extended::parse_oid;
+pub use extended::{is_extended_json_marker, EXTENDED_JSON_MARKERS};
+pub use json::{encode_json, encode_json_with};
 pub use object::create_document;
+pub use options::{
+    EncodeOptions, FieldType, UndefinedPolicy, DEFAULT_MAX_DEPTH, DEFAULT_MAX_DOCUMENT_SIZE,
+};
+
+pub(crate) use access::ValueAccess;
+pub(crate) use options::Context;
+
+pub(crate) type Result<T> = std::result::Result<T, EncodeError>;
+
+/// Encode any JsValue (object, array, string, number, boolean or null) into a BSON value,
+/// interpreting extended JSON type markers at every level
+pub fn encode(target: &JsValue) -> Result<Bson> {
+    encode_with(target, &EncodeOptions::default())
+}
+
+/// Encode any JsValue into a BSON value, see `EncodeOptions` for what can be tuned
+pub fn encode_with(target: &JsValue, options: &EncodeOptions) -> Result<Bson> {
+    value::encode_root(&mut Context::new(options), target)
+}
+
+/// Encode any JsValue into a BSON value like `encode_with`, pushing a message onto
+/// `warnings` for every conversion that loses information, e.g. a dropped regex
+/// flag or an integer too large to be represented exactly
+pub fn encode_with_warnings(
+    target: &JsValue,
+    options: &EncodeOptions,
+    warnings: &mut Vec<String>,
+) -> Result<Bson> {
+    value::encode_root(&mut Context::with_warnings(options, warnings), target)
+}
+
+/// Check that a JsValue would encode cleanly, running every check `encode` does
+/// (ObjectId hex, base64 payloads, date ranges, ...) without keeping the result.
+///
+/// Like every encoding error, a failure in a nested value is returned as
+/// `EncodeError::At`, locating it with a JSON-path like string, e.g.
+/// `$.users[2].created.$date`.
+pub fn validate(target: &JsValue) -> Result<()> {
+    encode(target)?;
+    Ok(())
+}
+
+/// Encode a JsValue and deserialize the resulting BSON into a typed rust value,
+/// e.g. a `#[derive(Deserialize)]` struct with an `ObjectId` field
+pub fn encode_to_serde<T: DeserializeOwned>(target: &JsValue) -> Result<T> {
+    Ok(bson::from_bson(encode(target)?)?)
+}
+
+/// Encode every element of an array as its own top level BSON document and
+/// serialize each one separately, as needed to insert many documents at once.
+///
+/// Stops at the first element that fails, returning `EncodeError::Element`
+/// with its index.
+pub fn encode_all(values: &js_sys::Array) -> Result<Vec<Vec<u8>>> {
+    let options = EncodeOptions::default();
+    let mut documents = Vec::with_capacity(values.length() as usize);
+    for (index, element) in values.iter().enumerate() {
+        let document = element_bytes(&options, &element).map_err(|error| EncodeError::Element {
+            index,
+            error: Box::new(error),
+        })?;
+        documents.push(document);
+    }
+    Ok(documents)
+}
+
+/// Encode and serialize a batch element, which must be a document
+fn element_bytes(options: &EncodeOptions, element: &JsValue) -> Result<Vec<u8>> {
+    match value::encode_root(&mut Context::new(options), element)? {
+        Bson::Document(document) => Ok(bson::to_vec(&document)?),
+        _ => Err(EncodeError::InvalidType {
+            field: "array element",
+            expected: "document",
+        }),
+    }
+}
+
+/// Encode every element of an array as its own BSON document and write the
+/// documents back to back, as expected by bulk inserts.
+///
+/// Elements are encoded and serialized one at a time, so only the BSON tree of
+/// the current element is ever held in memory (on top of whatever `writer` buffers),
+/// instead of a tree for the whole array.
+pub fn encode_array_to<W: Write>(array: &js_sys::Array, writer: &mut W) -> Result<()> {
+    let options = EncodeOptions::default();
+    for element in array.iter() {
+        match value::encode_root(&mut Context::new(&options), &element)? {
+            Bson::Document(document) => document.to_writer(&mut *writer)?,
+            _ => {
+                return Err(EncodeError::InvalidType {
+                    field: "array element",
+                    expected: "document",
+                })
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,43 +1,188 @@
-use wasm_bindgen::JsValue;
+use std::num::IntErrorKind;
 
-use crate::Result;
+use bson::Bson;
 
-/// Safely extract a JsValue into a rust String
-fn extract_string(target: &JsValue) -> Result<String> {
+use super::{Context, EncodeError, Result, ValueAccess};
+
+/// A numeric value, either a relaxed JS number or a canonical string
+enum Numeral {
+    Number(f64),
+    Text(String),
+}
+
+/// Extract a number or a string value so canonical (`{"$numberInt": "10"}`)
+/// and relaxed (`{"$numberInt": 10}`) inputs go through the same parsing
+fn numeral(target: &impl ValueAccess, field: &'static str) -> Result<Numeral> {
+    if let Some(n) = target.as_f64() {
+        return Ok(Numeral::Number(n));
+    }
     target
         .as_string()
-        .ok_or_else(|| "failed to extract string value".into())
+        .map(Numeral::Text)
+        .ok_or(EncodeError::InvalidType {
+            field,
+            expected: "number or numeric string",
+        })
+}
+
+/// Largest integer a JS number can represent exactly (2^53 - 1)
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Pick the BSON type of a bare JS number from its value:
+/// - integers within the i32 range become `Int32`, e.g. `42`
+/// - other safe integers (up to ±(2^53 - 1)) become `Int64`, e.g. `5000000000`
+/// - everything else stays a `Double`: fractions like `3.14`, `-0` (an integer
+///   type would lose its sign, while `0` is an `Int32`), NaN, infinities, and
+///   integers from 2^53 on, which may already have been rounded (with a warning)
+pub(crate) fn infer(ctx: &mut Context, n: f64) -> Bson {
+    // non-finite values come first, casting them to an integer would saturate
+    if !n.is_finite() || n.fract() != 0.0 || (n == 0.0 && n.is_sign_negative()) {
+        Bson::Double(n)
+    } else if n >= i32::MIN as f64 && n <= i32::MAX as f64 {
+        Bson::Int32(n as i32)
+    } else if n.abs() <= MAX_SAFE_INTEGER {
+        Bson::Int64(n as i64)
+    } else {
+        ctx.warn(|| {
+            format!(
+                "number {} coerced to Double, precision may be lost, use a BigInt or $numberLong",
+                n
+            )
+        });
+        Bson::Double(n)
+    }
+}
+
+/// The BSON value of an explicit `$numberDouble`, a `Double` unless
+/// `EncodeOptions::whole_doubles_as_ints` turns whole values into integers
+pub(crate) fn explicit_double(ctx: &mut Context, n: f64) -> Bson {
+    if ctx.options.whole_doubles_as_ints && n.abs() <= MAX_SAFE_INTEGER {
+        // fractions, -0 and non-finite values stay doubles
+        infer(ctx, n)
+    } else {
+        Bson::Double(n)
+    }
+}
+
+/// Parse a string, number or BigInt value into an i64
+/// Strings and BigInts keep their full 64-bit precision, JS numbers are only
+/// accepted when they are safe integers since anything above 2^53 may already
+/// have been rounded before it reached us.
+pub(crate) fn long(target: &impl ValueAccess) -> Result<i64> {
+    if let Some(n) = target.as_bigint() {
+        return n;
+    }
+    parse_long(numeral(target, "$numberLong")?)
 }
 
-/// Parse a string JsValue into an i64
-pub(crate) fn long(target: &JsValue) -> Result<i64> {
-    let n = extract_string(target)?;
-    let n = n
-        .parse::<i64>()
-        .map_err(|err| format!("error converting $numberLong value: {}", err.to_string()))?;
-    Ok(n)
+/// Parse a numeral into an i64, see `long`
+/// `str::parse` already accepts a leading `+`, e.g. `"+1565545664000"`, as some
+/// JSON generators write it.
+fn parse_long(numeral: Numeral) -> Result<i64> {
+    match numeral {
+        Numeral::Number(n) => {
+            if n.fract() != 0.0 || n.abs() > MAX_SAFE_INTEGER {
+                return Err(EncodeError::InvalidValue {
+                    field: "$numberLong",
+                    reason: format!("{} is not a safe integer", n),
+                });
+            }
+            Ok(n as i64)
+        }
+        Numeral::Text(n) => n.parse::<i64>().map_err(|err| EncodeError::InvalidValue {
+            field: "$numberLong",
+            reason: err.to_string(),
+        }),
+    }
+}
+
+/// Parse a number or string value into an i32
+/// Numbers are range checked instead of wrapping on cast.
+pub(crate) fn int(target: &impl ValueAccess) -> Result<i32> {
+    parse_int(numeral(target, "$numberInt")?)
+}
+
+/// Parse a numeral into an i32, see `int`
+fn parse_int(numeral: Numeral) -> Result<i32> {
+    match numeral {
+        Numeral::Number(n) => {
+            if n.fract() != 0.0 || n < i32::MIN as f64 || n > i32::MAX as f64 {
+                return Err(EncodeError::InvalidValue {
+                    field: "$numberInt",
+                    reason: format!("value {} out of range for $numberInt", n),
+                });
+            }
+            Ok(n as i32)
+        }
+        Numeral::Text(n) => n.parse::<i32>().map_err(|err| EncodeError::InvalidValue {
+            field: "$numberInt",
+            reason: match err.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                    format!("value {} out of range for $numberInt", n)
+                }
+                _ => err.to_string(),
+            },
+        }),
+    }
 }
 
-/// Parse a string JsValue into an i32
-pub(crate) fn int(target: &JsValue) -> Result<i32> {
-    let n = extract_string(target)?;
-    let n = n
-        .parse::<i32>()
-        .map_err(|err| format!("error converting $numberInt value: {}", err.to_string()))?;
-    Ok(n)
+/// Parse a number or string value into an f64
+/// Non-finite strings are only accepted as the canonical sentinels
+/// `"Infinity"`, `"-Infinity"` and `"NaN"`, not Rust's `inf`/`nan` spellings.
+pub(crate) fn double(target: &impl ValueAccess) -> Result<f64> {
+    parse_double(numeral(target, "$numberDouble")?)
 }
 
-/// Parse a string JsValue into an f64
-pub(crate) fn double(target: &JsValue) -> Result<f64> {
-    let n = extract_string(target)?;
+/// Parse a numeral into an f64, see `double`
+fn parse_double(numeral: Numeral) -> Result<f64> {
+    let n = match numeral {
+        Numeral::Number(n) => return Ok(n),
+        Numeral::Text(n) => n,
+    };
     match n.as_str() {
         "Infinity" => Ok(f64::INFINITY),
         "-Infinity" => Ok(f64::NEG_INFINITY),
+        "NaN" => Ok(f64::NAN),
         _ => {
-            let n = n.parse::<f64>().map_err(|err| {
-                format!("error converting $numberDouble value: {}", err.to_string())
+            let parsed = n.parse::<f64>().map_err(|err| EncodeError::InvalidValue {
+                field: "$numberDouble",
+                reason: err.to_string(),
             })?;
-            Ok(n)
+            if !parsed.is_finite() {
+                return Err(EncodeError::InvalidValue {
+                    field: "$numberDouble",
+                    reason: format!(
+                        "{:?} is not a valid double, expected \"Infinity\", \"-Infinity\" or \"NaN\"",
+                        n
+                    ),
+                });
+            }
+            Ok(parsed)
+        }
+    }
+}
+
+/// Parse a number or numeric string value into an u32, rejecting negative,
+/// fractional and out-of-range values instead of wrapping on cast
+pub(crate) fn uint(target: &impl ValueAccess, field: &'static str) -> Result<u32> {
+    parse_uint(numeral(target, field)?, field)
+}
+
+/// Parse a numeral into an u32, see `uint`
+fn parse_uint(numeral: Numeral, field: &'static str) -> Result<u32> {
+    match numeral {
+        Numeral::Number(n) => {
+            if n.fract() != 0.0 || n < 0.0 || n > u32::MAX as f64 {
+                return Err(EncodeError::InvalidValue {
+                    field,
+                    reason: format!("{} is out of range for an unsigned 32-bit integer", n),
+                });
+            }
+            Ok(n as u32)
         }
+        Numeral::Text(n) => n.parse::<u32>().map_err(|err| EncodeError::InvalidValue {
+            field,
+            reason: err.to_string(),
+        }),
     }
 }
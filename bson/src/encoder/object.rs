@@ -1,65 +1,264 @@
-use bson::{Bson, Document};
-use chrono::prelude::*;
+use bson::{spec::BinarySubtype, Bson, Document};
 use wasm_bindgen::{JsCast, JsValue};
 
-use super::{extended, value};
-use crate::Result;
+use super::{
+    class, extended, value, Context, EncodeError, EncodeOptions, FieldType, Result, ValueAccess,
+};
+use crate::keys;
 
 /// Inspect an object JsValue, taking into account default javascript values
-pub fn inspect(target: &JsValue) -> Result<Bson> {
-    if let Some(date) = target.dyn_ref::<js_sys::Date>() {
+pub fn inspect(ctx: &mut Context, target: &JsValue) -> Result<Bson> {
+    if let Some(primitive) = unbox(target) {
+        // new Number(5), new String("a"), new Boolean(true)
+        return value::encode_value(ctx, &primitive);
+    } else if let Some(date) = target.dyn_ref::<js_sys::Date>() {
         // Date
         let ms: f64 = date.get_time(); // [ms]
-        let secs = (ms / 1e3).floor() as i64; // [s]
-        let nsecs = ((ms % 1e3) * 1e6).ceil() as u32; // [ns]
-        let date = chrono::Utc.timestamp(secs, nsecs);
-        return Ok(Bson::DateTime(date));
+        if ms.is_nan() {
+            return Err(EncodeError::InvalidValue {
+                field: "Date",
+                reason: String::from("Invalid Date"),
+            });
+        }
+        // JS dates are always whole milliseconds within ±8.64e15
+        return Ok(Bson::DateTime(bson::DateTime::from_millis(ms as i64)));
+    } else if let Some(regex) = target.dyn_ref::<js_sys::RegExp>() {
+        // RegExp
+        let pattern = String::from(regex.source());
+        let options = extended::regex_options(ctx, &String::from(regex.flags()));
+        return Ok(Bson::RegularExpression(bson::Regex { pattern, options }));
+    } else if let Some(bytes) = target.dyn_ref::<js_sys::Uint8Array>() {
+        // Uint8Array
+        return Ok(binary(bytes));
+    } else if let Some(buffer) = target.dyn_ref::<js_sys::ArrayBuffer>() {
+        // ArrayBuffer
+        return Ok(binary(&js_sys::Uint8Array::new(buffer)));
     } else if let Some(iterable) = target.dyn_ref::<js_sys::Array>() {
-        // Array
-        let mut array = vec![];
-        for x in iterable.iter() {
-            array.push(value::inspect(&x)?)
+        // Array, elements are read with `Array::get` instead of `Reflect::get`
+        let mut array = Vec::with_capacity(iterable.length() as usize);
+        for (i, x) in iterable.iter().enumerate() {
+            array.push(ctx.at(|| format!("[{}]", i), |ctx| value::encode_value(ctx, &x))?)
         }
         return Ok(Bson::Array(array));
     } else if let Some(iterable) = target.dyn_ref::<js_sys::Set>() {
-        // Set
+        // Set, BSON has no set type so it becomes a plain array in insertion order;
+        // nothing keeps the stored array duplicate free once it's read back
         let mut array = vec![];
-        for x in iterable.keys() {
+        for (i, x) in iterable.keys().into_iter().enumerate() {
             let x = x?;
-            array.push(value::inspect(&x)?)
+            array.push(ctx.at(|| format!("[{}]", i), |ctx| value::encode_value(ctx, &x))?)
         }
         return Ok(Bson::Array(array));
     } else if let Some(map) = target.dyn_ref::<js_sys::Map>() {
-        // Map
+        // Map, fields keep the insertion order of the entries
         let mut document = Document::default();
-        for key in map.keys() {
-            let key = key?;
-            let val = map.get(&key);
-            let key = key
-                .as_string()
-                .ok_or_else(|| "only object can be serialized to bson documents")?;
-            document.insert(key, value::inspect(&val)?);
+        for entry in map.entries() {
+            let entry: js_sys::Array = entry?.unchecked_into();
+            let key = map_key(&entry.get(0))?;
+            let val = entry.get(1);
+            if ctx.skips(&val) {
+                continue;
+            }
+            if ctx.options.reject_duplicate_keys && document.contains_key(&key) {
+                return Err(EncodeError::DuplicateKey(key));
+            }
+            let val = match coerce(ctx, &key, &val) {
+                Some(val) => val,
+                None => ctx.at(|| format!(".{}", key), |ctx| value::encode_value(ctx, &val))?,
+            };
+            document.insert(key, val);
         }
         return Ok(Bson::Document(document));
-    } else if let Some(ext) = extended::inspect(target)? {
+    }
+
+    if let Some(wrapped) = class::inspect(target)? {
+        // Long, Int32, ... class instances
+        return Ok(wrapped);
+    }
+
+    encode_fields(ctx, fields(target)?)
+}
+
+/// Encode the fields of an object, either as an extended JSON value or as a plain
+/// document; shared with `encode_json` so JSON objects are read the same way
+pub(crate) fn encode_fields<V: ValueAccess>(ctx: &mut Context, fields: Fields<V>) -> Result<Bson> {
+    if let Some(ext) = extended::inspect(ctx, &fields)? {
         // { $type: ... } objects
         return Ok(ext);
     }
 
-    // Plain JS object
-    return Ok(Bson::Document(create_document(target)?));
+    if ctx.options.indexed_objects_as_arrays && is_indexed(&fields) {
+        // { "0": ..., "1": ... } objects
+        return Ok(Bson::Array(elements(ctx, fields)?));
+    }
+
+    // Plain object
+    Ok(Bson::Document(document(ctx, fields)?))
 }
 
-// Create a BSON decument from a pure javascript object
-pub fn create_document(target: &JsValue) -> Result<Document> {
-    let mut document = Document::default();
+/// The own string-keyed fields of an object, in enumeration order
+pub(crate) type Fields<V = JsValue> = Vec<(String, V)>;
+
+/// Extract every field of an object, enumerating its keys and reading
+/// each value exactly once so no helper needs to reflect on it again
+///
+/// Fields follow `Reflect.ownKeys` order, which is fixed by the language:
+/// integer-like keys (`"0"`, `"1"`, ...) come first in ascending numeric
+/// order, then the remaining keys in insertion order, so `{"2": .., "a": ..,
+/// "1": ..}` is always encoded as `{"1": .., "2": .., "a": ..}`. The original
+/// order of such an object cannot be recovered, pass a `Map` when the exact
+/// field order matters since its entries are encoded in insertion order.
+///
+/// Symbol keys are skipped without reading their value, BSON has no way to
+/// represent them and they are not part of the object's data in JSON either.
+pub(crate) fn fields(target: &JsValue) -> Result<Fields> {
     let keys = js_sys::Reflect::own_keys(target)?;
+    let mut fields = Vec::with_capacity(keys.length() as usize);
     for key in keys.iter() {
+        let name = match key.as_string() {
+            Some(name) => name,
+            None => continue,
+        };
         let val = js_sys::Reflect::get(target, &key)?;
-        let key = key
-            .as_string()
-            .ok_or_else(|| "failed to extract object key")?;
-        document.insert(key, value::inspect(&val)?);
+        fields.push((name, val));
+    }
+    Ok(fields)
+}
+
+/// The replacement of an object defining a `toBSON()` method, which the MongoDB
+/// drivers call before serializing it, e.g. on class instances
+pub(crate) fn to_bson(target: &JsValue) -> Result<Option<JsValue>> {
+    let method = keys::get(target, &keys::TO_BSON)?;
+    match method.dyn_ref::<js_sys::Function>() {
+        Some(method) => Ok(Some(method.call0(target)?)),
+        None => Ok(None),
+    }
+}
+
+/// Look up an already extracted field by name
+pub(crate) fn field<'a, V>(fields: &'a Fields<V>, name: &str) -> Option<&'a V> {
+    fields
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, val)| val)
+}
+
+/// Encode extracted fields as a plain document
+///
+/// Fields are encoded in a single loop, only nested objects recurse, so a wide
+/// flat document costs one pass over its fields. `bson::Document` can't be
+/// allocated with a capacity, its index grows as fields are inserted.
+fn document<V: ValueAccess>(ctx: &mut Context, fields: Fields<V>) -> Result<Document> {
+    let mut document = Document::new();
+    for (key, val) in fields {
+        if ctx.skips(&val) {
+            continue;
+        }
+        let val = match coerce(ctx, &key, &val) {
+            Some(val) => val,
+            None => ctx.at(|| format!(".{}", key), |ctx| val.encode(ctx))?,
+        };
+        document.insert(key, val);
     }
     Ok(document)
 }
+
+/// A field value coerced to the type `EncodeOptions::field_types` gives its name,
+/// `None` when there's no type for the name or the value doesn't fit it
+fn coerce(ctx: &Context, key: &str, val: &impl ValueAccess) -> Option<Bson> {
+    let field_types = &ctx.options.field_types;
+    if field_types.is_empty() {
+        return None;
+    }
+    match field_types.get(key)? {
+        FieldType::ObjectId => {
+            let hex = val.as_string()?;
+            extended::parse_oid(&hex).ok().map(Bson::ObjectId)
+        }
+        FieldType::DateTime => {
+            // the range of a javascript Date, always whole milliseconds
+            let ms = val
+                .as_f64()
+                .filter(|ms| ms.fract() == 0.0 && ms.abs() <= 8.64e15)?;
+            Some(Bson::DateTime(bson::DateTime::from_millis(ms as i64)))
+        }
+    }
+}
+
+/// Whether the keys of an object are exactly `"0"`, `"1"`, ..., `"<n - 1>"`
+/// with `n > 0`; integer-like keys are enumerated first in ascending order, so
+/// any other key or a gap shows up as a mismatch
+fn is_indexed<V>(fields: &Fields<V>) -> bool {
+    !fields.is_empty()
+        && fields
+            .iter()
+            .enumerate()
+            .all(|(i, (key, _))| *key == i.to_string())
+}
+
+/// Encode the values of index-keyed fields as array elements
+fn elements<V: ValueAccess>(ctx: &mut Context, fields: Fields<V>) -> Result<Vec<Bson>> {
+    let mut array = Vec::with_capacity(fields.len());
+    for (i, (_, val)) in fields.into_iter().enumerate() {
+        array.push(ctx.at(|| format!("[{}]", i), |ctx| val.encode(ctx))?)
+    }
+    Ok(array)
+}
+
+/// The primitive value of a boxed `Number`, `String` or `Boolean`, which would
+/// otherwise be encoded as an empty document
+fn unbox(target: &JsValue) -> Option<JsValue> {
+    // `dyn_ref` only recognizes the primitives themselves for these types
+    if target.is_instance_of::<js_sys::Number>() {
+        Some(target.unchecked_ref::<js_sys::Number>().value_of().into())
+    } else if target.is_instance_of::<js_sys::JsString>() {
+        Some(target.unchecked_ref::<js_sys::JsString>().value_of().into())
+    } else if target.is_instance_of::<js_sys::Boolean>() {
+        Some(target.unchecked_ref::<js_sys::Boolean>().value_of().into())
+    } else {
+        None
+    }
+}
+
+/// Stringify a Map key the way javascript would for primitive keys
+fn map_key(key: &JsValue) -> Result<String> {
+    if let Some(key) = key.as_string() {
+        Ok(key)
+    } else if let Some(b) = key.as_bool() {
+        Ok(b.to_string())
+    } else if key.as_f64().is_some() || key.is_bigint() {
+        let key: &js_sys::Object = key.unchecked_ref();
+        Ok(String::from(key.to_string()))
+    } else {
+        Err(EncodeError::InvalidType {
+            field: "Map key",
+            expected: "string, number, boolean or bigint",
+        })
+    }
+}
+
+/// Copy the bytes of a typed array into a generic BSON binary
+///
+/// `to_vec` copies straight from the javascript view into an exactly sized `Vec`,
+/// the only copy needed since `bson::Binary` owns its bytes; an `ArrayBuffer` is
+/// viewed without copying it first.
+fn binary(bytes: &js_sys::Uint8Array) -> Bson {
+    Bson::Binary(bson::Binary {
+        bytes: bytes.to_vec(),
+        subtype: BinarySubtype::Generic,
+    })
+}
+
+// Create a BSON decument from a pure javascript object
+pub fn create_document(target: &JsValue, options: &EncodeOptions) -> Result<Document> {
+    let target = to_bson(target)?.unwrap_or_else(|| target.clone());
+    if !target.is_object() {
+        return Err(EncodeError::InvalidType {
+            field: "toBSON() result",
+            expected: "object",
+        });
+    }
+    Context::new(options)
+        .nested(|ctx| document(ctx, fields(&target)?))
+        .map_err(EncodeError::rooted)
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use super::{EncodeError, Result, ValueAccess};
+
+/// Default for `EncodeOptions::max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 200;
+
+/// Largest document accepted by a MongoDB server by default, in bytes
+pub const DEFAULT_MAX_DOCUMENT_SIZE: usize = 16 * 1024 * 1024;
+
+/// What a javascript `undefined` is encoded as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UndefinedPolicy {
+    /// `Bson::Null`, like an explicit `null`
+    Null,
+    /// The deprecated `Bson::Undefined`
+    Undefined,
+    /// Leave out document fields and Map entries holding `undefined`, like the
+    /// MongoDB drivers ignoring undefined; array elements and top level values
+    /// can't be left out and become `Bson::Null`, as in `JSON.stringify`
+    Skip,
+}
+
+/// The BSON type a field is coerced to, see `EncodeOptions::field_types`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    /// A string holding a valid 24-char hex ObjectId becomes an `ObjectId`
+    ObjectId,
+    /// A number holding whole milliseconds since the Unix epoch, within the range
+    /// of a javascript `Date`, becomes a `DateTime`
+    DateTime,
+}
+
+/// Options controlling how JsValues are encoded into BSON
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Maximum nesting of objects and arrays, deeper values fail with
+    /// `EncodeError::DepthExceeded` instead of overflowing the stack
+    pub max_depth: usize,
+    /// How `undefined` values are encoded, `UndefinedPolicy::Skip` by default
+    pub undefined: UndefinedPolicy,
+    /// Encode plain objects keyed by array indexes, e.g. `{"0": a, "1": b}`, as
+    /// `Bson::Array`s, for data from serializers that lose the array/object
+    /// distinction. Off by default since it's a heuristic.
+    ///
+    /// An object is converted when it has at least one key and its keys are
+    /// exactly `"0"`, `"1"`, ..., `"<n - 1>"`: zero-based, contiguous, without
+    /// gaps and in canonical form (`"01"` or `"+1"` don't count). Extended JSON
+    /// wrappers, `Map`s and the top level value of `to_bson_document` are never
+    /// converted.
+    pub indexed_objects_as_arrays: bool,
+    /// Reject objects whose only key is an unknown `$`-prefixed key, e.g. the typo
+    /// `{"$objectId": "..."}`, with `EncodeError::UnknownMarker` instead of encoding
+    /// them as a document with a `$objectId` field. Off by default, query and
+    /// update operators such as `{"$set": {...}}` are such objects too.
+    pub strict_markers: bool,
+    /// Accept `$regularExpression` patterns holding an unescaped `/`, which can't be
+    /// written as a legacy `/pattern/flags` literal, with a warning instead of failing.
+    /// BSON itself allows such patterns and the decoder emits them as stored, so set
+    /// this to encode decoded data back.
+    pub lenient_regex: bool,
+    /// Encode whole `$numberDouble` values, e.g. `{"$numberDouble": "5.0"}`, as
+    /// `Int32` or `Int64` like a bare number, as some relaxed to canonical tooling
+    /// does. Off by default: a `$numberDouble` says the value was explicitly a
+    /// double, which is lost, and the value reads back as an integer. Fractions,
+    /// `-0`, non-finite values and integers beyond ±(2^53 - 1) stay doubles.
+    ///
+    /// Bare javascript numbers don't need this, `5.0` and `5` are the same number
+    /// and already become an `Int32`.
+    pub whole_doubles_as_ints: bool,
+    /// Fail with `EncodeError::DuplicateKey` when two entries of a `Map` have the
+    /// same key once stringified, e.g. `1` and `"1"`, instead of keeping the value
+    /// of the last one. On by default, such keys are almost always a bug.
+    pub reject_duplicate_keys: bool,
+    /// Types to coerce fields to by name, e.g. `"_id"` to `FieldType::ObjectId`, for
+    /// data that stores them as plain strings or numbers. Applies to document fields
+    /// and `Map` entries at any depth. Empty by default.
+    ///
+    /// Only a plain value of the expected shape is coerced: any other value,
+    /// including an explicit marker such as `{"$oid": ...}`, a `Date` or a class
+    /// instance, is encoded as usual, so markers always take precedence. A value
+    /// that doesn't fit the type, e.g. a string `_id` that isn't an ObjectId hex,
+    /// is encoded as usual too, without failing.
+    pub field_types: HashMap<String, FieldType>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        EncodeOptions {
+            max_depth: DEFAULT_MAX_DEPTH,
+            undefined: UndefinedPolicy::Skip,
+            indexed_objects_as_arrays: false,
+            strict_markers: false,
+            lenient_regex: false,
+            whole_doubles_as_ints: false,
+            reject_duplicate_keys: true,
+            field_types: HashMap::new(),
+        }
+    }
+}
+
+/// State threaded through every recursive encoding step
+pub(crate) struct Context<'a> {
+    pub(crate) options: &'a EncodeOptions,
+    depth: usize,
+    warnings: Option<&'a mut Vec<String>>,
+}
+
+impl<'a> Context<'a> {
+    pub(crate) fn new(options: &'a EncodeOptions) -> Self {
+        Context {
+            options,
+            depth: 0,
+            warnings: None,
+        }
+    }
+
+    /// Like `new`, but collect a message for every lossy conversion into `warnings`
+    pub(crate) fn with_warnings(options: &'a EncodeOptions, warnings: &'a mut Vec<String>) -> Self {
+        Context {
+            options,
+            depth: 0,
+            warnings: Some(warnings),
+        }
+    }
+
+    /// Whether a field holding `target` is left out of its document
+    pub(crate) fn skips(&self, target: &impl ValueAccess) -> bool {
+        self.options.undefined == UndefinedPolicy::Skip && target.is_undefined()
+    }
+
+    /// Record a lossy conversion, the message is only built when someone listens
+    pub(crate) fn warn(&mut self, message: impl FnOnce() -> String) {
+        if let Some(warnings) = self.warnings.as_mut() {
+            warnings.push(message());
+        }
+    }
+
+    /// Run `f` for the value found at `segment` (e.g. `.name` or `[2]`) of the current
+    /// value, prefixing the path of its error with the segment. The segment is only
+    /// formatted on failure, so successful encoding pays nothing for paths.
+    pub(crate) fn at<T>(
+        &mut self,
+        segment: impl FnOnce() -> String,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        f(self).map_err(|error| match error {
+            EncodeError::At { path, error } => EncodeError::At {
+                path: segment() + &path,
+                error,
+            },
+            error => EncodeError::At {
+                path: segment(),
+                error: Box::new(error),
+            },
+        })
+    }
+
+    /// Run `f` one nesting level deeper, failing once `max_depth` is exceeded
+    pub(crate) fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= self.options.max_depth {
+            return Err(EncodeError::DepthExceeded(self.options.max_depth));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+}
@@ -1,21 +1,52 @@
 use bson::Bson;
 use wasm_bindgen::JsValue;
 
-use super::object;
-use crate::Result;
+use super::{number, object, Context, EncodeError, Result, UndefinedPolicy};
 
-/// Inspect a generic JsValue, taking into account default javascript values
-pub fn inspect(target: &JsValue) -> Result<Bson> {
+/// Encode a top level JsValue, see `encode_value`
+pub(crate) fn encode_root(ctx: &mut Context, target: &JsValue) -> Result<Bson> {
+    encode_value(ctx, target).map_err(EncodeError::rooted)
+}
+
+/// Encode a generic JsValue, taking into account default javascript values
+/// Every recursive step (array items, document fields, values nested in
+/// extended JSON markers) goes through here, so behavior is the same at any depth
+/// and every object counts towards the nesting limit
+///
+/// Objects with a `toBSON()` method are replaced by its result first. The result is
+/// encoded as is, even if it has a `toBSON()` of its own (e.g. `return this`), so a
+/// value is never replaced twice; values nested in it are replaced as usual.
+pub(crate) fn encode_value(ctx: &mut Context, target: &JsValue) -> Result<Bson> {
+    if target.is_object() {
+        if let Some(replaced) = object::to_bson(target)? {
+            return encode_as_is(ctx, &replaced);
+        }
+    }
+    encode_as_is(ctx, target)
+}
+
+/// Encode a JsValue without looking for a `toBSON()` method on it
+fn encode_as_is(ctx: &mut Context, target: &JsValue) -> Result<Bson> {
     if let Some(n) = target.as_f64() {
-        return Ok(Bson::Double(n));
+        return Ok(number::infer(ctx, n));
+    } else if target.is_bigint() {
+        return Ok(Bson::Int64(number::long(target)?));
     } else if target.is_string() {
         return Ok(Bson::String(target.as_string().unwrap()));
     } else if let Some(b) = target.as_bool() {
         return Ok(Bson::Boolean(b));
     } else if target.is_null() {
         return Ok(Bson::Null);
+    } else if target.is_undefined() {
+        // skipped fields never get here, anything else can't be left out
+        return Ok(match ctx.options.undefined {
+            UndefinedPolicy::Undefined => Bson::Undefined,
+            UndefinedPolicy::Null | UndefinedPolicy::Skip => Bson::Null,
+        });
     } else if target.is_object() {
-        return Ok(object::inspect(target)?);
+        return ctx.nested(|ctx| object::inspect(ctx, target));
     }
-    Err("type not valid in BSON spec".into())
+    Err(EncodeError::Unsupported(
+        target.js_typeof().as_string().unwrap_or_default(),
+    ))
 }
@@ -0,0 +1,97 @@
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+use crate::encoder::error_object;
+use crate::EncodeError;
+
+/// Error returned by every fallible function of this crate, converted into a
+/// javascript exception when it crosses the WASM boundary
+#[derive(Debug)]
+pub enum Error {
+    /// A JsValue can't be encoded into BSON
+    Encode(EncodeError),
+    /// A BSON value can't be decoded, or another failure described by a message
+    Message(String),
+    /// An exception thrown by the javascript runtime
+    Js(JsValue),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Encode(err) => write!(f, "{}", err),
+            Error::Message(message) => write!(f, "{}", message),
+            // same message as an exception raised while encoding
+            Error::Js(err) => write!(f, "{}", EncodeError::Js(err.clone())),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Encode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        match err {
+            // keep a single variant for javascript exceptions
+            EncodeError::Js(err) => Error::Js(err),
+            err => Error::Encode(err),
+        }
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Message(message.to_owned())
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Message(message)
+    }
+}
+
+impl From<JsValue> for Error {
+    fn from(err: JsValue) -> Self {
+        Error::Js(err)
+    }
+}
+
+impl From<js_sys::Error> for Error {
+    fn from(err: js_sys::Error) -> Self {
+        Error::Js(err.into())
+    }
+}
+
+impl Error {
+    /// Convert into the value thrown by the structured wasm entry points: a
+    /// `{code, message, path}` object (see `EncodeError::to_js_object`), while
+    /// javascript exceptions are rethrown untouched
+    pub fn into_structured(self) -> JsValue {
+        match self {
+            Error::Encode(err) if err.is_js() => err.into(),
+            Error::Encode(err) => err.to_js_object(),
+            Error::Message(message) => error_object("error", &message, None),
+            Error::Js(err) => err,
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Encode(err) => err.into(),
+            Error::Message(message) => js_sys::Error::new(&message).into(),
+            // rethrow javascript exceptions untouched
+            Error::Js(err) => err,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Property keys read and written for every wrapped value, created once per thread
+//
+// Every `JsValue::from_str` copies the string across the wasm boundary into a
+// new javascript string; reading and writing the same few keys for every
+// wrapped value reuses these instead.
+
+use std::thread::LocalKey;
+
+use wasm_bindgen::JsValue;
+
+/// A property key, along with its javascript string cached for the current thread
+pub(crate) struct Key {
+    /// The key itself
+    pub(crate) name: &'static str,
+    cached: &'static LocalKey<JsValue>,
+}
+
+macro_rules! keys {
+    ($($name:ident = $key:literal),* $(,)?) => {
+        mod cached {
+            use wasm_bindgen::JsValue;
+
+            thread_local! {
+                $(pub(super) static $name: JsValue = JsValue::from_str($key);)*
+            }
+        }
+
+        $(pub(crate) static $name: Key = Key { name: $key, cached: &cached::$name };)*
+    };
+}
+
+keys! {
+    OID = "$oid",
+    DATE = "$date",
+    NUMBER_DOUBLE = "$numberDouble",
+    NUMBER_INT = "$numberInt",
+    NUMBER_LONG = "$numberLong",
+    NUMBER_DECIMAL = "$numberDecimal",
+    REGULAR_EXPRESSION = "$regularExpression",
+    BINARY = "$binary",
+    TIMESTAMP = "$timestamp",
+    MIN_KEY = "$minKey",
+    MAX_KEY = "$maxKey",
+    SYMBOL = "$symbol",
+    UNDEFINED = "$undefined",
+    DB_POINTER = "$dbPointer",
+    CODE = "$code",
+    SCOPE = "$scope",
+    REF = "$ref",
+    ID = "$id",
+    PATTERN = "pattern",
+    OPTIONS = "options",
+    BASE64 = "base64",
+    SUB_TYPE = "subType",
+    T = "t",
+    I = "i",
+    ERROR_CODE = "code",
+    ERROR_MESSAGE = "message",
+    ERROR_PATH = "path",
+    TO_BSON = "toBSON",
+    BSON_TYPE = "_bsontype",
+    LOW = "low",
+    HIGH = "high",
+    UNSIGNED = "unsigned",
+    VALUE = "value",
+    BYTES = "bytes",
+    TO_HEX_STRING = "toHexString",
+}
+
+/// `Reflect.get(target, key)`
+pub(crate) fn get(target: &JsValue, key: &Key) -> Result<JsValue, JsValue> {
+    key.cached.with(|key| js_sys::Reflect::get(target, key))
+}
+
+/// `Reflect.set(target, key, value)`
+pub(crate) fn set(target: &JsValue, key: &Key, value: &JsValue) -> Result<bool, JsValue> {
+    key.cached
+        .with(|key| js_sys::Reflect::set(target, key, value))
+}
@@ -1,32 +1,172 @@
+use bson::Bson;
 use wasm_bindgen::prelude::*;
 
-type Result<T> = std::result::Result<T, JsValue>;
+/// Result of every fallible function of this crate
+pub type Result<T> = std::result::Result<T, Error>;
 
+mod decoder;
 mod encoder;
+mod error;
+mod keys;
+
+pub use error::Error;
+
+pub use decoder::{
+    decode, decode_with, decode_with_options, oid_timestamp, BsonStreamDecoder, DateFormat,
+    DecodeOptions, ExtJsonMode,
+};
+pub use encoder::{
+    encode, encode_all, encode_array_to, encode_json, encode_json_with, encode_to_serde,
+    encode_with, encode_with_warnings, is_extended_json_marker, validate, EncodeError,
+    EncodeOptions, FieldType, UndefinedPolicy, DEFAULT_MAX_DEPTH, DEFAULT_MAX_DOCUMENT_SIZE,
+    EXTENDED_JSON_MARKERS,
+};
 
 #[wasm_bindgen]
 pub fn to_bson_document(target: &JsValue) -> Result<Vec<u8>> {
     if !target.is_object() {
         return Err(js_sys::Error::new("only object can be serialized to bson documents").into());
     }
-    let document = encoder::create_document(&target)?;
-    let mut buf: Vec<u8> = vec![];
-    document.to_writer(&mut buf).map_err(|err| {
-        js_sys::Error::new(&format!("error writing document: {}", err.to_string()))
+    let document = encoder::create_document(target, &EncodeOptions::default())?;
+    // `Document::to_writer` serializes into a buffer of its own and copies it into
+    // the writer, serializing directly keeps a single copy of large binaries
+    Ok(bson::to_vec(&document).map_err(EncodeError::Serialize)?)
+}
+
+/// Encode a JsValue into raw BSON bytes, the top level value must be a document.
+///
+/// The serialized document may be at most `max_size` bytes, defaulting to
+/// `DEFAULT_MAX_DOCUMENT_SIZE`; pass a larger limit for servers configured with one.
+#[wasm_bindgen]
+pub fn to_bytes(value: &JsValue, max_size: Option<usize>) -> Result<Vec<u8>> {
+    match encoder::encode(value)? {
+        Bson::Document(document) => serialize(&document, max_size),
+        _ => Err(EncodeError::InvalidType {
+            field: "top level value",
+            expected: "document",
+        }
+        .into()),
+    }
+}
+
+/// Like `to_bytes`, but failures throw a `{code, message, path}` object instead of
+/// an `Error` with a message, e.g. `{code: "invalid_type", message: "invalid $oid:
+/// expected 24-char hex string", path: "$.user._id.$oid"}`
+#[wasm_bindgen]
+pub fn try_to_bytes(
+    value: &JsValue,
+    max_size: Option<usize>,
+) -> std::result::Result<Vec<u8>, JsValue> {
+    to_bytes(value, max_size).map_err(Error::into_structured)
+}
+
+/// Like `to_bson_document`, but failures throw a `{code, message, path}` object,
+/// see `try_to_bytes`
+#[wasm_bindgen]
+pub fn try_to_bson_document(target: &JsValue) -> std::result::Result<Vec<u8>, JsValue> {
+    if !target.is_object() {
+        let err = EncodeError::InvalidType {
+            field: "top level value",
+            expected: "object",
+        };
+        return Err(err.to_js_object());
+    }
+    to_bson_document(target).map_err(Error::into_structured)
+}
+
+/// Encode a javascript array into raw BSON bytes, wrapped in a document keyed by
+/// the element indexes (`"0"`, `"1"`, ...).
+///
+/// This is exactly how a `Bson::Array` is stored when nested in a document, so the
+/// bytes are the same as those of the array field of `{"a": [...]}`. The size limit
+/// works as in `to_bytes`.
+#[wasm_bindgen]
+pub fn to_bytes_array(array: &js_sys::Array, max_size: Option<usize>) -> Result<Vec<u8>> {
+    match encoder::encode(array)? {
+        Bson::Array(elements) => {
+            let document: bson::Document = elements
+                .into_iter()
+                .enumerate()
+                .map(|(index, element)| (index.to_string(), element))
+                .collect();
+            serialize(&document, max_size)
+        }
+        // an array with a `toBSON()` method may be replaced by anything
+        _ => Err(EncodeError::InvalidType {
+            field: "top level value",
+            expected: "array",
+        }
+        .into()),
+    }
+}
+
+/// Serialize a document, failing when it's larger than `max_size` bytes
+fn serialize(document: &bson::Document, max_size: Option<usize>) -> Result<Vec<u8>> {
+    let bytes = bson::to_vec(document).map_err(EncodeError::Serialize)?;
+    if bytes.len() > max_size.unwrap_or(DEFAULT_MAX_DOCUMENT_SIZE) {
+        return Err(EncodeError::DocumentTooLarge { size: bytes.len() }.into());
+    }
+    Ok(bytes)
+}
+
+/// Build the `{"$binary": {"base64": ..., "subType": ...}}` wrapper of a payload,
+/// with the subtype spelled as zero-padded two-character hex (e.g. `6` is `"06"`)
+#[wasm_bindgen]
+pub fn make_binary(bytes: &[u8], subtype: u8) -> Result<JsValue> {
+    decoder::binary(&bson::Binary {
+        bytes: bytes.to_vec(),
+        subtype: subtype.into(),
+    })
+}
+
+/// Validate the 24-char hex string of an ObjectId and build its `{"$oid": ...}`
+/// wrapper, spelled in lowercase like every decoded `$oid`
+#[wasm_bindgen]
+pub fn parse_object_id(s: &str) -> Result<JsValue> {
+    decoder::oid(&encoder::parse_oid(s)?)
+}
+
+/// Generate a fresh ObjectId, from the current time and a random process id and
+/// counter, and build its `{"$oid": ...}` wrapper
+#[wasm_bindgen]
+pub fn new_object_id() -> Result<JsValue> {
+    decoder::oid(&bson::oid::ObjectId::new())
+}
+
+/// Parse an extended JSON string and encode it into raw BSON bytes
+#[wasm_bindgen]
+pub fn encode_extended_json(json: &str) -> Result<Vec<u8>> {
+    let value = js_sys::JSON::parse(json).map_err(|err| EncodeError::InvalidValue {
+        field: "extended JSON string",
+        reason: EncodeError::Js(err).to_string(),
     })?;
-    Ok(buf)
+    to_bytes(&value, None)
+}
+
+/// Normalize a JsValue into canonical extended JSON text: the value is encoded into
+/// BSON, decoded back and stringified, e.g. `{"n": 1, "d": {"$date": "2020-01-01T00:00:00Z"}}`
+/// becomes `{"n":{"$numberInt":"1"},"d":{"$date":{"$numberLong":"1577836800000"}}}`
+#[wasm_bindgen]
+pub fn to_extended_json_string(value: &JsValue) -> Result<String> {
+    let value = decoder::decode(&encoder::encode(value)?)?;
+    let json = js_sys::JSON::stringify(&value)?;
+    Ok(json.into())
+}
+
+/// Decode raw BSON bytes holding a single document into canonical extended JSON
+#[wasm_bindgen]
+pub fn from_bytes(bytes: &[u8]) -> Result<JsValue> {
+    let document = bson::Document::from_reader(bytes).map_err(EncodeError::Deserialize)?;
+    decoder::decode(&Bson::Document(document))
 }
 
 #[wasm_bindgen]
 pub fn from_bson_document(buf: Vec<u8>) -> Result<JsValue> {
     let mut x: &[u8] = &buf;
-    let document = bson::Document::from_reader(&mut x).map_err(|err| {
-        js_sys::Error::new(&format!("error parsing document: {}", err.to_string()))
+    let document = bson::Document::from_reader(&mut x)
+        .map_err(|err| js_sys::Error::new(&format!("error parsing document: {}", err)))?;
+    let json = serde_json::to_string(&document).map_err(|err| {
+        js_sys::Error::new(&format!("error serializing document to json: {}", err))
     })?;
-    Ok(JsValue::from_serde(&document).map_err(|err| {
-        js_sys::Error::new(&format!(
-            "error serializing document to json: {}",
-            err.to_string()
-        ))
-    })?)
+    Ok(js_sys::JSON::parse(&json)?)
 }
@@ -0,0 +1,233 @@
+//! Decoder options, run with `wasm-pack test --node`, the decoder needs a javascript runtime.
+#![cfg(target_arch = "wasm32")]
+
+use bson::{spec::BinarySubtype, Bson};
+use mango_bson::{
+    decode_with_options, from_bytes, oid_timestamp, to_bytes, BsonStreamDecoder, DateFormat,
+    DecodeOptions, ExtJsonMode,
+};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// Decode a date with `options` and return the content of its `$date` wrapper as JSON
+fn date_json(millis: i64, options: &DecodeOptions) -> String {
+    let date = Bson::DateTime(bson::DateTime::from_millis(millis));
+    let value = decode_with_options(&date, options).unwrap();
+    let date = js_sys::Reflect::get(&value, &JsValue::from_str("$date")).unwrap();
+    String::from(js_sys::JSON::stringify(&date).unwrap())
+}
+
+#[wasm_bindgen_test]
+fn date_follows_mode_by_default() {
+    assert_eq!(
+        date_json(1_577_836_800_250, &DecodeOptions::default()),
+        r#"{"$numberLong":"1577836800250"}"#
+    );
+    assert_eq!(
+        date_json(1_577_836_800_250, &DecodeOptions::new(ExtJsonMode::Relaxed)),
+        r#""2020-01-01T00:00:00.250Z""#
+    );
+}
+
+#[wasm_bindgen_test]
+fn date_formats() {
+    let with = |date_format| DecodeOptions {
+        date_format: Some(date_format),
+        ..DecodeOptions::new(ExtJsonMode::Relaxed)
+    };
+    assert_eq!(
+        date_json(1_577_836_800_250, &with(DateFormat::EpochMillis)),
+        r#"{"$numberLong":"1577836800250"}"#
+    );
+    assert_eq!(
+        date_json(1_577_836_800_250, &with(DateFormat::Rfc3339)),
+        r#""2020-01-01T00:00:00.250Z""#
+    );
+    // only as many fractional digits as needed
+    assert_eq!(
+        date_json(1_577_836_800_000, &with(DateFormat::Rfc3339)),
+        r#""2020-01-01T00:00:00Z""#
+    );
+    assert_eq!(
+        date_json(1_577_836_800_000, &with(DateFormat::Rfc3339WithMillis)),
+        r#""2020-01-01T00:00:00.000Z""#
+    );
+    assert_eq!(
+        date_json(1_577_836_800_250, &with(DateFormat::Rfc3339WithMillis)),
+        r#""2020-01-01T00:00:00.250Z""#
+    );
+}
+
+#[wasm_bindgen_test]
+fn dates_without_4_digit_year_stay_canonical() {
+    let relaxed = DecodeOptions::new(ExtJsonMode::Relaxed);
+    // 9999-12-31T23:59:59.999Z
+    assert_eq!(
+        date_json(253_402_300_799_999, &relaxed),
+        r#""9999-12-31T23:59:59.999Z""#
+    );
+    // 10000-01-01T00:00:00Z
+    assert_eq!(
+        date_json(253_402_300_800_000, &relaxed),
+        r#"{"$numberLong":"253402300800000"}"#
+    );
+    // -0001-12-31T23:59:59.999Z
+    assert_eq!(
+        date_json(-62_167_219_200_001, &relaxed),
+        r#"{"$numberLong":"-62167219200001"}"#
+    );
+}
+
+#[wasm_bindgen_test]
+fn preserve_order_decodes_documents_into_maps() {
+    let document = Bson::Document(bson::doc! {"b": 1, "1": {"a": true}, "0": "x"});
+    let options = DecodeOptions {
+        preserve_order: true,
+        ..DecodeOptions::new(ExtJsonMode::Relaxed)
+    };
+    let value = decode_with_options(&document, &options).unwrap();
+    let map: &js_sys::Map = value.dyn_ref().expect("a Map");
+    let keys: Vec<_> = map
+        .keys()
+        .into_iter()
+        .map(|key| key.unwrap().as_string().unwrap())
+        .collect();
+    assert_eq!(keys, vec!["b", "1", "0"]);
+    assert!(map
+        .get(&JsValue::from_str("1"))
+        .is_instance_of::<js_sys::Map>());
+
+    // plain objects put integer-like keys first
+    let value = decode_with_options(&document, &DecodeOptions::default()).unwrap();
+    let keys: Vec<_> = js_sys::Object::keys(value.unchecked_ref::<js_sys::Object>())
+        .iter()
+        .map(|key| key.as_string().unwrap())
+        .collect();
+    assert_eq!(keys, vec!["0", "1", "b"]);
+}
+
+#[wasm_bindgen_test]
+fn binary_as_typed_array() {
+    let options = DecodeOptions {
+        binary_as_typed_array: true,
+        ..DecodeOptions::default()
+    };
+    let generic = Bson::Binary(bson::Binary {
+        bytes: vec![1, 2, 3],
+        subtype: BinarySubtype::Generic,
+    });
+    let value = decode_with_options(&generic, &options).unwrap();
+    let bytes: &js_sys::Uint8Array = value.dyn_ref().expect("a Uint8Array");
+    assert_eq!(bytes.to_vec(), vec![1, 2, 3]);
+
+    // other subtypes keep their wrapper
+    let uuid = Bson::Binary(bson::Binary {
+        bytes: vec![0; 16],
+        subtype: BinarySubtype::Uuid,
+    });
+    let value = decode_with_options(&uuid, &options).unwrap();
+    assert!(js_sys::Reflect::has(&value, &JsValue::from_str("$binary")).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn wrapper_keys_follow_the_spec_order() {
+    let document = Bson::Document(bson::doc! {
+        "binary": bson::Binary { bytes: vec![1, 2, 3], subtype: BinarySubtype::Uuid },
+        "regex": bson::Regex { pattern: String::from("^a"), options: String::from("i") },
+        "timestamp": bson::Timestamp { time: 1, increment: 2 },
+        "code": Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+            code: String::from("x"),
+            scope: bson::doc! {"x": 1},
+        }),
+    });
+    let value = decode_with_options(&document, &DecodeOptions::default()).unwrap();
+    assert_eq!(
+        String::from(js_sys::JSON::stringify(&value).unwrap()),
+        concat!(
+            r#"{"binary":{"$binary":{"base64":"AQID","subType":"04"}},"#,
+            r#""regex":{"$regularExpression":{"pattern":"^a","options":"i"}},"#,
+            r#""timestamp":{"$timestamp":{"t":1,"i":2}},"#,
+            r#""code":{"$code":"x","$scope":{"x":{"$numberInt":"1"}}}}"#,
+        )
+    );
+}
+
+#[wasm_bindgen_test]
+fn proto_field_is_an_own_property() {
+    let document = Bson::Document(bson::doc! {"__proto__": {"polluted": true}, "a": "x"});
+    let value = decode_with_options(&document, &DecodeOptions::new(ExtJsonMode::Relaxed)).unwrap();
+    assert_eq!(
+        js_sys::Object::get_prototype_of(&value),
+        js_sys::Object::get_prototype_of(&js_sys::Object::new())
+    );
+    assert!(js_sys::Object::has_own(
+        value.unchecked_ref::<js_sys::Object>(),
+        &JsValue::from_str("__proto__")
+    ));
+    assert!(js_sys::Reflect::get(&value, &JsValue::from_str("polluted"))
+        .unwrap()
+        .is_undefined());
+    assert_eq!(
+        String::from(js_sys::JSON::stringify(&value).unwrap()),
+        r#"{"__proto__":{"polluted":true},"a":"x"}"#
+    );
+}
+
+/// Raw bytes of `{"n": <n>}`
+fn document_bytes(n: i32) -> Vec<u8> {
+    bson::to_vec(&bson::doc! {"n": n}).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn stream_decodes_documents_one_at_a_time() {
+    let mut stream = document_bytes(1);
+    stream.extend(document_bytes(2));
+    let values: Vec<String> =
+        BsonStreamDecoder::with_options(&stream[..], DecodeOptions::new(ExtJsonMode::Relaxed))
+            .map(|value| String::from(js_sys::JSON::stringify(&value.unwrap()).unwrap()))
+            .collect();
+    assert_eq!(values, vec![r#"{"n":1}"#, r#"{"n":2}"#]);
+    assert!(BsonStreamDecoder::new(&[][..]).next().is_none());
+}
+
+#[wasm_bindgen_test]
+fn stream_rejects_truncated_trailing_document() {
+    for cut in [2, 9] {
+        let mut stream = document_bytes(1);
+        stream.extend(&document_bytes(2)[..cut]);
+        let mut decoder = BsonStreamDecoder::new(&stream[..]);
+        assert!(decoder.next().unwrap().is_ok());
+        let err = decoder.next().unwrap().unwrap_err();
+        assert!(err.to_string().starts_with("truncated document"), "{}", err);
+        assert!(decoder.next().is_none());
+    }
+}
+
+#[wasm_bindgen_test]
+fn from_bytes_reverses_to_bytes() {
+    let json = concat!(
+        r#"{"n":{"$numberInt":"1"},"s":"x","#,
+        r#""id":{"$oid":"5f3d2a1b9c8e7d6f5a4b3c2d"},"#,
+        r#""d":{"$date":{"$numberLong":"1577836800250"}},"a":[true,null]}"#
+    );
+    let value = js_sys::JSON::parse(json).unwrap();
+    let decoded = from_bytes(&to_bytes(&value, None).unwrap()).unwrap();
+    assert_eq!(
+        String::from(js_sys::JSON::stringify(&decoded).unwrap()),
+        json
+    );
+
+    let bytes = to_bytes(&value, None).unwrap();
+    assert!(from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn object_id_timestamps() {
+    let oid = |hex| bson::oid::ObjectId::parse_str(hex).unwrap();
+    assert_eq!(oid_timestamp(&oid("5f3d2a1b9c8e7d6f5a4b3c2d")), 0x5f3d2a1b);
+    // the seconds are unsigned, past 2038 they stay positive
+    assert_eq!(
+        oid_timestamp(&oid("ffffffff9c8e7d6f5a4b3c2d")),
+        i64::from(u32::MAX)
+    );
+}
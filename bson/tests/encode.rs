@@ -0,0 +1,795 @@
+//! Encoder behavior a decode/encode round trip can't cover: options, warnings,
+//! batches and javascript values without an extended JSON counterpart.
+//!
+//! Run with `wasm-pack test --node`, the encoder needs a javascript runtime.
+#![cfg(target_arch = "wasm32")]
+
+use std::collections::HashMap;
+
+use bson::{doc, Bson};
+use mango_bson::{
+    encode, encode_all, encode_array_to, encode_extended_json, encode_to_serde, encode_with,
+    encode_with_warnings, to_bson_document, to_bytes, to_bytes_array, try_to_bson_document,
+    try_to_bytes, validate, EncodeError, EncodeOptions, FieldType, UndefinedPolicy,
+    DEFAULT_MAX_DEPTH,
+};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn symbol_keys_are_skipped() {
+    let object = js_sys::Object::new();
+    js_sys::Reflect::set(&object, &JsValue::from_str("name"), &JsValue::from_str("x")).unwrap();
+    js_sys::Reflect::set(&object, &js_sys::Symbol::for_("hidden"), &JsValue::TRUE).unwrap();
+    assert_eq!(encode(&object).unwrap(), Bson::Document(doc! {"name": "x"}));
+}
+
+#[wasm_bindgen_test]
+// 3.14 is just a fractional number, not an approximation of PI
+#[allow(clippy::approx_constant)]
+fn number_inference() {
+    assert_eq!(encode(&JsValue::from_f64(42.0)).unwrap(), Bson::Int32(42));
+    assert_eq!(
+        encode(&JsValue::from_f64(5e9)).unwrap(),
+        Bson::Int64(5_000_000_000)
+    );
+    assert_eq!(
+        encode(&JsValue::from_f64(3.14)).unwrap(),
+        Bson::Double(3.14)
+    );
+    // -0 keeps its sign as a double, 2^53 may already be rounded
+    assert_eq!(
+        encode(&JsValue::from_f64(-0.0)).unwrap(),
+        Bson::Double(-0.0)
+    );
+    assert_eq!(
+        encode(&JsValue::from_f64(9_007_199_254_740_992.0)).unwrap(),
+        Bson::Double(9_007_199_254_740_992.0)
+    );
+}
+
+#[wasm_bindgen_test]
+fn non_finite_numbers_are_doubles() {
+    assert!(matches!(
+        encode(&JsValue::from_f64(f64::NAN)).unwrap(),
+        Bson::Double(n) if n.is_nan()
+    ));
+    assert_eq!(
+        encode(&JsValue::from_f64(f64::INFINITY)).unwrap(),
+        Bson::Double(f64::INFINITY)
+    );
+    assert_eq!(
+        encode(&JsValue::from_f64(f64::NEG_INFINITY)).unwrap(),
+        Bson::Double(f64::NEG_INFINITY)
+    );
+}
+
+#[wasm_bindgen_test]
+fn number_long_keeps_64_bit_precision() {
+    // 2^53 + 1 has no exact double, going through f64 would give 2^53
+    let value = js_sys::JSON::parse(r#"{"$numberLong": "9007199254740993"}"#).unwrap();
+    assert_eq!(encode(&value).unwrap(), Bson::Int64(9_007_199_254_740_993));
+}
+
+#[wasm_bindgen_test]
+fn bigints_are_longs() {
+    let max = eval("9223372036854775807n");
+    assert_eq!(encode(&max).unwrap(), Bson::Int64(i64::MAX));
+    let wrapped = eval("{n: {$numberLong: -9223372036854775808n}}");
+    assert_eq!(
+        encode(&wrapped).unwrap(),
+        Bson::Document(doc! {"n": i64::MIN})
+    );
+
+    let err = encode(&eval("{n: 9223372036854775808n}")).unwrap_err();
+    assert_eq!(err.code(), "invalid_value");
+    assert_eq!(err.path().as_deref(), Some("$.n"));
+    let err = encode(&eval("{$numberLong: -9223372036854775809n}")).unwrap_err();
+    assert_eq!(err.path().as_deref(), Some("$.$numberLong"));
+}
+
+#[wasm_bindgen_test]
+fn number_int_range() {
+    let parse = |json| encode(&js_sys::JSON::parse(json).unwrap());
+    assert_eq!(
+        parse(r#"{"$numberInt": "-2147483648"}"#).unwrap(),
+        Bson::Int32(i32::MIN)
+    );
+    assert_eq!(
+        parse(r#"{"$numberInt": 2147483647}"#).unwrap(),
+        Bson::Int32(i32::MAX)
+    );
+    for overflow in [
+        r#"{"$numberInt": "2147483648"}"#,
+        r#"{"$numberInt": -2147483649}"#,
+        r#"{"$numberInt": "3000000000"}"#,
+    ] {
+        let err = parse(overflow).unwrap_err();
+        assert_eq!(err.code(), "invalid_value");
+        assert!(err.to_string().contains("out of range for $numberInt"));
+    }
+}
+
+#[wasm_bindgen_test]
+fn numeric_markers_take_strings_and_numbers() {
+    let parse = |json| encode(&js_sys::JSON::parse(json).unwrap()).unwrap();
+    for (canonical, relaxed, expected) in [
+        (
+            r#"{"$numberInt": "10"}"#,
+            r#"{"$numberInt": 10}"#,
+            Bson::Int32(10),
+        ),
+        (
+            r#"{"$numberLong": "10"}"#,
+            r#"{"$numberLong": 10}"#,
+            Bson::Int64(10),
+        ),
+        (
+            r#"{"$numberDouble": "10.5"}"#,
+            r#"{"$numberDouble": 10.5}"#,
+            Bson::Double(10.5),
+        ),
+    ] {
+        assert_eq!(parse(canonical), expected);
+        assert_eq!(parse(relaxed), expected);
+    }
+}
+
+#[wasm_bindgen_test]
+fn min_max_key_values() {
+    let parse = |json| encode(&js_sys::JSON::parse(json).unwrap());
+    assert_eq!(parse(r#"{"$minKey": 1}"#).unwrap(), Bson::MinKey);
+    assert_eq!(parse(r#"{"$maxKey": 1}"#).unwrap(), Bson::MaxKey);
+    let err = parse(r#"{"$minKey": true}"#).unwrap_err();
+    assert_eq!(err.code(), "invalid_type");
+    assert_eq!(err.path().as_deref(), Some("$.$minKey"));
+}
+
+/// Evaluate a javascript expression
+fn eval(expression: &str) -> JsValue {
+    js_sys::Function::new_no_args(&format!("return {}", expression))
+        .call0(&JsValue::NULL)
+        .unwrap()
+}
+
+#[wasm_bindgen_test]
+fn to_bson_replaces_objects() {
+    let long = eval(r#"{n: {toBSON() { return {$numberLong: "5"}; }}}"#);
+    assert_eq!(encode(&long).unwrap(), Bson::Document(doc! {"n": 5_i64}));
+
+    // the replacement isn't replaced again, even when it's the object itself
+    let itself = eval("{n: Object.assign(Object.create({toBSON() { return this; }}), {a: 1})}");
+    assert_eq!(
+        encode(&itself).unwrap(),
+        Bson::Document(doc! {"n": {"a": 1}})
+    );
+
+    let string = eval(r#"{n: {toBSON: () => "x"}}"#);
+    assert_eq!(encode(&string).unwrap(), Bson::Document(doc! {"n": "x"}));
+}
+
+#[wasm_bindgen_test]
+fn bson_library_classes() {
+    // minimal stand-ins for the classes of the `bson` package, typed by their prototype
+    let value = eval(
+        r#"(() => {
+            const typed = (type, fields) =>
+                Object.assign(Object.create({_bsontype: type}), fields);
+            const oid = typed("ObjectId", {});
+            Object.getPrototypeOf(oid).toHexString = () => "5f3d2a1b9c8e7d6f5a4b3c2d";
+            return {
+                long: typed("Long", {low: -1, high: 1, unsigned: false}),
+                negative: typed("Long", {low: -2, high: -1, unsigned: false}),
+                int: typed("Int32", {value: 7}),
+                double: typed("Double", {value: 1}),
+                decimal: typed("Decimal128", {bytes: new Uint8Array([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x40, 0x30])}),
+                oid,
+            };
+        })()"#,
+    );
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Document(doc! {
+            "long": 0x1_ffff_ffff_i64,
+            "negative": -2_i64,
+            "int": 7,
+            "double": 1.0,
+            "decimal": Bson::Decimal128("1".parse().unwrap()),
+            "oid": bson::oid::ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap(),
+        })
+    );
+
+    let unsigned = eval(
+        r#"Object.assign(Object.create({_bsontype: "Long"}), {low: 0, high: -1, unsigned: true})"#,
+    );
+    assert_eq!(encode(&unsigned).unwrap_err().code(), "invalid_value");
+}
+
+#[wasm_bindgen_test]
+fn object_id_spellings() {
+    let expected =
+        Bson::ObjectId(bson::oid::ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap());
+    // js-bson 5+
+    let current = eval(
+        r#"(() => {
+            class ObjectId {
+                get _bsontype() { return "ObjectId"; }
+                toHexString() { return "5f3d2a1b9c8e7d6f5a4b3c2d"; }
+            }
+            return new ObjectId();
+        })()"#,
+    );
+    assert_eq!(encode(&current).unwrap(), expected);
+    // js-bson 4.x and older, with the id under a symbol key
+    let legacy = eval(
+        r#"(() => {
+            const id = Symbol("id");
+            class ObjectID {
+                constructor() { this[id] = "5f3d2a1b9c8e7d6f5a4b3c2d"; }
+                toHexString() { return this[id]; }
+            }
+            ObjectID.prototype._bsontype = "ObjectID";
+            return new ObjectID();
+        })()"#,
+    );
+    assert_eq!(encode(&legacy).unwrap(), expected);
+}
+
+#[wasm_bindgen_test]
+fn strict_markers() {
+    let typo = eval(r#"{"id": {"$objectId": "5f3d2a1b9c8e7d6f5a4b3c2d"}}"#);
+    assert_eq!(
+        encode(&typo).unwrap(),
+        Bson::Document(doc! {"id": {"$objectId": "5f3d2a1b9c8e7d6f5a4b3c2d"}})
+    );
+
+    let options = EncodeOptions {
+        strict_markers: true,
+        ..EncodeOptions::default()
+    };
+    let err = encode_with(&typo, &options).unwrap_err();
+    assert_eq!(err.code(), "unknown_marker");
+    assert_eq!(err.path().as_deref(), Some("$.id"));
+    assert_eq!(
+        err.cause().to_string(),
+        "unknown extended JSON type: $objectId"
+    );
+    // only lone keys are markers, and known markers still work
+    let mixed = eval(r#"{"$objectId": 1, "name": "x", "at": {"$date": {"$numberLong": "0"}}}"#);
+    assert!(encode_with(&mixed, &options).is_ok());
+}
+
+#[wasm_bindgen_test]
+fn deep_nesting_is_an_error() {
+    let deep = eval("(() => { let v = {}; for (let i = 0; i < 500; i++) v = {v}; return v; })()");
+    let err = encode(&deep).unwrap_err();
+    assert_eq!(err.code(), "depth_exceeded");
+    assert!(matches!(
+        err.cause(),
+        EncodeError::DepthExceeded(max) if *max == DEFAULT_MAX_DEPTH
+    ));
+}
+
+#[wasm_bindgen_test]
+fn nested_scopes_share_the_depth_budget() {
+    let value = eval(r#"{"$code": "f()", "$scope": {"f": {"$code": "g()", "$scope": {"g": 1}}}}"#);
+    let options = |max_depth| EncodeOptions {
+        max_depth,
+        ..EncodeOptions::default()
+    };
+    assert!(encode_with(&value, &options(4)).is_ok());
+    let err = encode_with(&value, &options(3)).unwrap_err();
+    assert_eq!(err.code(), "depth_exceeded");
+    assert_eq!(err.path().as_deref(), Some("$.$scope.f.$scope"));
+}
+
+#[wasm_bindgen_test]
+fn lenient_regex() {
+    let value = eval(r#"{"$regularExpression": {"pattern": "a/b", "options": ""}}"#);
+    assert_eq!(encode(&value).unwrap_err().code(), "invalid_value");
+
+    let options = EncodeOptions {
+        lenient_regex: true,
+        ..EncodeOptions::default()
+    };
+    let mut warnings = vec![];
+    let regex = encode_with_warnings(&value, &options, &mut warnings).unwrap();
+    assert_eq!(
+        regex,
+        Bson::RegularExpression(bson::Regex {
+            pattern: String::from("a/b"),
+            options: String::new(),
+        })
+    );
+    assert_eq!(warnings, vec!["unescaped '/' at index 1 of regex pattern"]);
+}
+
+#[wasm_bindgen_test]
+fn whole_doubles_as_ints() {
+    let value = eval(
+        r#"{"five": {"$numberDouble": "5.0"}, "big": {"$numberDouble": "5e9"}, "half": {"$numberDouble": "0.5"}, "bare": 5.0}"#,
+    );
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Document(doc! {"five": 5.0, "big": 5e9, "half": 0.5, "bare": 5})
+    );
+    let options = EncodeOptions {
+        whole_doubles_as_ints: true,
+        ..EncodeOptions::default()
+    };
+    assert_eq!(
+        encode_with(&value, &options).unwrap(),
+        Bson::Document(doc! {"five": 5, "big": 5_000_000_000_i64, "half": 0.5, "bare": 5})
+    );
+}
+
+#[wasm_bindgen_test]
+fn field_types_coerce_plain_values() {
+    let options = EncodeOptions {
+        field_types: HashMap::from([
+            (String::from("_id"), FieldType::ObjectId),
+            (String::from("createdAt"), FieldType::DateTime),
+        ]),
+        ..EncodeOptions::default()
+    };
+    let oid = bson::oid::ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap();
+    let value = eval(
+        r#"{
+            "_id": "5f3d2a1b9c8e7d6f5a4b3c2d",
+            "createdAt": 1565545664000,
+            "nested": {"_id": "user-1", "createdAt": 1.5},
+            "map": new Map([["_id", "5f3d2a1b9c8e7d6f5a4b3c2d"]])
+        }"#,
+    );
+    assert_eq!(
+        encode_with(&value, &options).unwrap(),
+        Bson::Document(doc! {
+            "_id": oid,
+            "createdAt": bson::DateTime::from_millis(1565545664000),
+            "nested": {"_id": "user-1", "createdAt": 1.5},
+            "map": {"_id": oid},
+        })
+    );
+
+    // explicit markers take precedence over the field type
+    let marked = eval(r#"{"createdAt": {"$numberLong": "5"}}"#);
+    assert_eq!(
+        encode_with(&marked, &options).unwrap(),
+        Bson::Document(doc! {"createdAt": 5_i64})
+    );
+}
+
+/// The keys of an encoded document in order, `Document` equality ignores it
+fn keys(bson: Bson) -> Vec<String> {
+    match bson {
+        Bson::Document(document) => document.keys().cloned().collect(),
+        other => panic!("expected a document, got {:?}", other),
+    }
+}
+
+#[wasm_bindgen_test]
+fn maps_keep_insertion_order() {
+    let map = eval(r#"new Map([["b", 1], ["a", 2]])"#);
+    assert_eq!(keys(encode(&map).unwrap()), ["b", "a"]);
+}
+
+#[wasm_bindgen_test]
+fn object_keys_follow_enumeration_order() {
+    // integer-like keys come first in ascending order, then the others as written
+    let object = eval(r#"{"2": 1, "a": 2, "1": 3}"#);
+    assert_eq!(keys(encode(&object).unwrap()), ["1", "2", "a"]);
+    let map = eval(r#"new Map([["2", 1], ["a", 2], ["1", 3]])"#);
+    assert_eq!(keys(encode(&map).unwrap()), ["2", "a", "1"]);
+}
+
+#[wasm_bindgen_test]
+fn duplicate_map_keys() {
+    let value = eval(r#"{"m": new Map([[1, "number"], ["1", "string"]])}"#);
+    let err = encode(&value).unwrap_err();
+    assert_eq!(err.code(), "duplicate_key");
+    assert_eq!(err.path().as_deref(), Some("$.m"));
+    assert_eq!(err.cause().to_string(), r#"duplicate key "1""#);
+
+    let options = EncodeOptions {
+        reject_duplicate_keys: false,
+        ..EncodeOptions::default()
+    };
+    assert_eq!(
+        encode_with(&value, &options).unwrap(),
+        Bson::Document(doc! {"m": {"1": "string"}})
+    );
+}
+
+#[wasm_bindgen_test]
+fn boxed_primitives() {
+    let boxed = eval(
+        r#"{"n": new Number(5), "x": new Number(1.5), "s": new String("a"), "b": new Boolean(false)}"#,
+    );
+    let primitives = eval(r#"{"n": 5, "x": 1.5, "s": "a", "b": false}"#);
+    assert_eq!(encode(&boxed).unwrap(), encode(&primitives).unwrap());
+    assert_eq!(
+        encode(&boxed).unwrap(),
+        Bson::Document(doc! {"n": 5, "x": 1.5, "s": "a", "b": false})
+    );
+}
+
+#[wasm_bindgen_test]
+fn lossy_conversions_are_warned_about() {
+    let object = js_sys::Object::new();
+    let regex = js_sys::RegExp::new("^a", "yi");
+    js_sys::Reflect::set(&object, &JsValue::from_str("re"), &regex).unwrap();
+    js_sys::Reflect::set(&object, &JsValue::from_str("n"), &JsValue::from_f64(2e16)).unwrap();
+
+    let mut warnings = vec![];
+    encode_with_warnings(&object, &EncodeOptions::default(), &mut warnings).unwrap();
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(warnings[0], "dropped regex flag 'y'");
+    assert!(warnings[1].starts_with("number 20000000000000000 coerced to Double"));
+}
+
+#[wasm_bindgen_test]
+fn indexed_objects_as_arrays() {
+    let options = EncodeOptions {
+        indexed_objects_as_arrays: true,
+        ..EncodeOptions::default()
+    };
+    let parse = |json: &str, options: &EncodeOptions| {
+        encode_with(&js_sys::JSON::parse(json).unwrap(), options).unwrap()
+    };
+    assert_eq!(
+        parse(r#"{"a": {"1": "y", "0": "x"}}"#, &options),
+        Bson::Document(doc! {"a": ["x", "y"]})
+    );
+    // off by default
+    assert_eq!(
+        parse(r#"{"a": {"0": "x"}}"#, &EncodeOptions::default()),
+        Bson::Document(doc! {"a": {"0": "x"}})
+    );
+    // gaps, other keys, non-canonical indexes and empty objects stay documents
+    for json in [
+        r#"{"0": 1, "2": 2}"#,
+        r#"{"1": 1}"#,
+        r#"{"0": 1, "a": 2}"#,
+        r#"{"00": 1}"#,
+        r#"{}"#,
+    ] {
+        let object = format!(r#"{{"a": {}}}"#, json);
+        assert!(matches!(
+            parse(&object, &options),
+            Bson::Document(ref document) if matches!(document.get("a"), Some(Bson::Document(_)))
+        ));
+    }
+}
+
+fn with_undefined(policy: UndefinedPolicy) -> Bson {
+    let object = js_sys::Object::new();
+    js_sys::Reflect::set(&object, &JsValue::from_str("a"), &JsValue::UNDEFINED).unwrap();
+    let array = js_sys::Array::of1(&JsValue::UNDEFINED);
+    js_sys::Reflect::set(&object, &JsValue::from_str("b"), &array).unwrap();
+    let options = EncodeOptions {
+        undefined: policy,
+        ..EncodeOptions::default()
+    };
+    encode_with(&object, &options).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn undefined_as_null() {
+    assert_eq!(
+        with_undefined(UndefinedPolicy::Null),
+        Bson::Document(doc! {"a": Bson::Null, "b": [Bson::Null]})
+    );
+}
+
+#[wasm_bindgen_test]
+fn undefined_as_undefined() {
+    assert_eq!(
+        with_undefined(UndefinedPolicy::Undefined),
+        Bson::Document(doc! {"a": Bson::Undefined, "b": [Bson::Undefined]})
+    );
+}
+
+#[wasm_bindgen_test]
+fn undefined_skipped() {
+    // array elements can't be left out without shifting the others
+    assert_eq!(
+        with_undefined(UndefinedPolicy::Skip),
+        Bson::Document(doc! {"b": [Bson::Null]})
+    );
+}
+
+#[wasm_bindgen_test]
+fn binary_without_subtype_is_generic() {
+    let value = js_sys::JSON::parse(r#"{"$binary": {"base64": "aGVsbG8="}}"#).unwrap();
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Binary(bson::Binary {
+            bytes: b"hello".to_vec(),
+            subtype: bson::spec::BinarySubtype::Generic,
+        })
+    );
+}
+
+#[wasm_bindgen_test]
+fn batch_reports_the_failing_index() {
+    let values = js_sys::JSON::parse(r#"[{"a": 1}, {"b": 2}]"#).unwrap();
+    let documents = encode_all(values.unchecked_ref()).unwrap();
+    assert_eq!(
+        documents,
+        vec![
+            bson::to_vec(&doc! {"a": 1}).unwrap(),
+            bson::to_vec(&doc! {"b": 2}).unwrap(),
+        ]
+    );
+
+    let values = js_sys::JSON::parse(r#"[{"a": 1}, {"_id": {"$oid": "xyz"}}]"#).unwrap();
+    match encode_all(values.unchecked_ref()) {
+        Err(EncodeError::Element { index: 1, .. }) => {}
+        other => panic!("expected an error at element 1, got {:?}", other),
+    }
+}
+
+/// A writer keeping count of the documents and bytes written, but not the bytes
+#[derive(Default)]
+struct Counter {
+    writes: usize,
+    bytes: usize,
+}
+
+impl std::io::Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writes += 1;
+        self.bytes += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[wasm_bindgen_test]
+fn large_arrays_are_streamed() {
+    let values = eval("Array.from({length: 100000}, (_, i) => ({i}))");
+    let mut counter = Counter::default();
+    encode_array_to(values.unchecked_ref(), &mut counter).unwrap();
+    // every document is `{"i": <int32>}`, 12 bytes
+    assert_eq!(counter.bytes, 100_000 * 12);
+    assert!(counter.writes >= 100_000);
+
+    let values = eval(r#"[{a: 1}, "b"]"#);
+    let err = encode_array_to(values.unchecked_ref(), &mut Counter::default()).unwrap_err();
+    assert_eq!(err.code(), "invalid_type");
+}
+
+#[wasm_bindgen_test]
+fn validate_locates_the_failing_value() {
+    let value = js_sys::JSON::parse(
+        r#"{"users": [{}, {}, {"created": {"$date": "not a date"}}], "ok": true}"#,
+    )
+    .unwrap();
+    match validate(&value) {
+        Err(EncodeError::At { path, error }) => {
+            assert_eq!(path, "$.users[2].created.$date");
+            assert!(matches!(
+                *error,
+                EncodeError::InvalidValue { field: "$date", .. }
+            ));
+        }
+        other => panic!("expected a located error, got {:?}", other),
+    }
+
+    let value = js_sys::JSON::parse(r#"{"users": [{"name": "x"}]}"#).unwrap();
+    assert!(validate(&value).is_ok());
+}
+
+#[wasm_bindgen_test]
+fn date_shapes() {
+    let date = Bson::DateTime(bson::DateTime::from_millis(1_565_545_664_000));
+    let encode_json = |json| encode(&js_sys::JSON::parse(json).unwrap());
+    assert_eq!(
+        encode_json(r#"{"$date": {"$numberLong": "1565545664000"}}"#).unwrap(),
+        date
+    );
+    // older drivers write the milliseconds as a number
+    assert_eq!(
+        encode_json(r#"{"$date": {"$numberLong": 1565545664000}}"#).unwrap(),
+        date
+    );
+    assert_eq!(
+        encode_json(r#"{"$date": "2019-08-11T17:47:44Z"}"#).unwrap(),
+        date
+    );
+    assert!(encode_json(r#"{"$date": 1565545664000}"#)
+        .unwrap_err()
+        .to_string()
+        .ends_with(r#"expected a string or {"$numberLong": "<millis>"}"#));
+}
+
+#[wasm_bindgen_test]
+fn structured_errors() {
+    let value = js_sys::JSON::parse(r#"{"user": {"_id": {"$oid": "xyz"}}}"#).unwrap();
+    let thrown = try_to_bytes(&value, None).unwrap_err();
+    let get = |key: &str| js_sys::Reflect::get(&thrown, &JsValue::from_str(key)).unwrap();
+    assert_eq!(get("code").as_string().unwrap(), "invalid_type");
+    assert_eq!(
+        get("message").as_string().unwrap(),
+        "invalid $oid: expected 24-char hex string"
+    );
+    assert_eq!(get("path").as_string().unwrap(), "$.user._id.$oid");
+}
+
+#[wasm_bindgen_test]
+fn bytes_array_replaced_by_to_bson() {
+    let array: js_sys::Array =
+        eval(r#"Object.assign([1, 2], {toBSON() { return {"a": 1}; }})"#).unchecked_into();
+    let err = to_bytes_array(&array, None).unwrap_err();
+    assert_eq!(err.to_string(), "invalid top level value: expected array");
+
+    let plain: js_sys::Array = eval("[1, 2]").unchecked_into();
+    assert_eq!(
+        to_bytes_array(&plain, None).unwrap(),
+        bson::to_vec(&doc! {"0": 1, "1": 2}).unwrap()
+    );
+}
+
+#[wasm_bindgen_test]
+fn encode_into_a_typed_struct() {
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct User {
+        #[serde(rename = "_id")]
+        id: bson::oid::ObjectId,
+        name: String,
+    }
+
+    let value = eval(r#"{_id: {$oid: "5f3d2a1b9c8e7d6f5a4b3c2d"}, name: "x"}"#);
+    assert_eq!(
+        encode_to_serde::<User>(&value).unwrap(),
+        User {
+            id: bson::oid::ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap(),
+            name: String::from("x"),
+        }
+    );
+    let err = encode_to_serde::<User>(&eval(r#"{_id: "not an ObjectId", name: "x"}"#)).unwrap_err();
+    assert_eq!(err.code(), "deserialize");
+}
+
+#[wasm_bindgen_test]
+fn dates() {
+    let value = eval("{d: new Date(1565545664000)}");
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Document(doc! {"d": bson::DateTime::from_millis(1_565_545_664_000)})
+    );
+    let err = encode(&eval("{d: new Date(NaN)}")).unwrap_err();
+    assert_eq!(err.code(), "invalid_value");
+    assert_eq!(err.path().as_deref(), Some("$.d"));
+    assert_eq!(err.cause().to_string(), "invalid Date: Invalid Date");
+}
+
+#[wasm_bindgen_test]
+fn byte_arrays_are_binaries() {
+    let binary = |bytes: &[u8]| {
+        Bson::Binary(bson::Binary {
+            bytes: bytes.to_vec(),
+            subtype: bson::spec::BinarySubtype::Generic,
+        })
+    };
+    // a view only holds its own range of the underlying buffer
+    let value =
+        eval("{a: new Uint8Array([0, 1, 2, 255]).subarray(1), b: new Uint8Array([1, 2]).buffer}");
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Document(doc! {"a": binary(&[1, 2, 255]), "b": binary(&[1, 2])})
+    );
+
+    let err = to_bytes(&eval("{a: new Uint8Array(64)}"), Some(32)).unwrap_err();
+    assert!(matches!(
+        err,
+        mango_bson::Error::Encode(EncodeError::DocumentTooLarge { .. })
+    ));
+}
+
+#[wasm_bindgen_test]
+fn sets_are_arrays() {
+    let value = eval(r#"{s: new Set(["b", 1, "a"])}"#);
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Document(doc! {"s": ["b", 1, "a"]})
+    );
+    let err = encode(&eval(r#"{s: new Set(["a", {$oid: "x"}])}"#)).unwrap_err();
+    assert_eq!(err.code(), "invalid_type");
+    assert_eq!(err.path().as_deref(), Some("$.s[1].$oid"));
+}
+
+#[wasm_bindgen_test]
+fn serialization_errors_are_structured() {
+    // BSON keys are null terminated, they can't hold a null character
+    let value = eval(r#"{"a\u0000b": 1}"#);
+    let thrown = try_to_bson_document(&value).unwrap_err();
+    let code = js_sys::Reflect::get(&thrown, &JsValue::from_str("code")).unwrap();
+    assert_eq!(code.as_string().unwrap(), "serialize");
+    assert!(matches!(
+        to_bson_document(&value),
+        Err(mango_bson::Error::Encode(EncodeError::Serialize(_)))
+    ));
+}
+
+#[wasm_bindgen_test]
+fn nested_markers_in_dbrefs_and_scopes() {
+    let oid = bson::oid::ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap();
+    let dbref = eval(r#"{$ref: "users", $id: {$oid: "5f3d2a1b9c8e7d6f5a4b3c2d"}}"#);
+    let document = match encode(&dbref).unwrap() {
+        Bson::Document(document) => document,
+        other => panic!("expected a document, got {:?}", other),
+    };
+    assert_eq!(document.get("$id"), Some(&Bson::ObjectId(oid)));
+
+    let code = eval(r#"{$code: "f(at)", $scope: {at: {$date: {$numberLong: "0"}}}}"#);
+    assert_eq!(
+        encode(&code).unwrap(),
+        Bson::JavaScriptCodeWithScope(bson::JavaScriptCodeWithScope {
+            code: String::from("f(at)"),
+            scope: doc! {"at": bson::DateTime::from_millis(0)},
+        })
+    );
+}
+
+#[wasm_bindgen_test]
+fn binary_subtype_names() {
+    use bson::spec::BinarySubtype;
+
+    let binary = |subtype: &str| {
+        let json = format!(
+            r#"{{"$binary": {{"base64": "AQI=", "subType": "{}"}}}}"#,
+            subtype
+        );
+        encode(&js_sys::JSON::parse(&json).unwrap())
+    };
+    for (name, subtype) in [
+        ("generic", BinarySubtype::Generic),
+        ("function", BinarySubtype::Function),
+        ("binaryOld", BinarySubtype::BinaryOld),
+        ("uuidOld", BinarySubtype::UuidOld),
+        ("uuid", BinarySubtype::Uuid),
+        ("md5", BinarySubtype::Md5),
+        ("encrypted", BinarySubtype::Encrypted),
+        ("userDefined", BinarySubtype::UserDefined(0x80)),
+        // names are case insensitive
+        ("UUID", BinarySubtype::Uuid),
+        ("Md5", BinarySubtype::Md5),
+    ] {
+        assert_eq!(
+            binary(name).unwrap(),
+            Bson::Binary(bson::Binary {
+                bytes: vec![1, 2],
+                subtype,
+            }),
+            "{}",
+            name
+        );
+    }
+
+    let err = binary("sha256").unwrap_err();
+    assert_eq!(err.code(), "invalid_value");
+    assert_eq!(
+        err.cause().to_string(),
+        "invalid $binary.subType: unknown subtype \"sha256\", expected a hex byte or one of \
+         generic, function, binaryOld, uuidOld, uuid, md5, encrypted, userDefined"
+    );
+}
+
+#[wasm_bindgen_test]
+fn extended_json_strings() {
+    let json = r#"{"_id": {"$oid": "5f3d2a1b9c8e7d6f5a4b3c2d"}, "n": {"$numberLong": "5"}}"#;
+    assert_eq!(
+        encode_extended_json(json).unwrap(),
+        to_bytes(&js_sys::JSON::parse(json).unwrap(), None).unwrap()
+    );
+
+    // the message of the SyntaxError thrown by JSON.parse, e.g. in node
+    // `Unexpected token '}', "{"n": }" is not valid JSON`
+    let err = encode_extended_json(r#"{"n": }"#).unwrap_err().to_string();
+    assert!(err.starts_with("invalid extended JSON string: "), "{}", err);
+    assert!(err.contains("is not valid JSON"), "{}", err);
+}
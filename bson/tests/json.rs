@@ -0,0 +1,435 @@
+//! Extended JSON encoding of `serde_json::Value`, run natively with `cargo test`
+
+use bson::{doc, oid::ObjectId, spec::BinarySubtype, Bson};
+use mango_bson::{
+    encode_json, encode_json_with, is_extended_json_marker, EncodeError, EncodeOptions,
+    DEFAULT_MAX_DEPTH, EXTENDED_JSON_MARKERS,
+};
+use serde_json::json;
+
+#[test]
+fn plain_values() {
+    assert_eq!(encode_json(&json!(null)).unwrap(), Bson::Null);
+    assert_eq!(encode_json(&json!(true)).unwrap(), Bson::Boolean(true));
+    assert_eq!(
+        encode_json(&json!("mango")).unwrap(),
+        Bson::String("mango".into())
+    );
+}
+
+#[test]
+// 3.14 is just a fractional number, not an approximation of PI
+#[allow(clippy::approx_constant)]
+fn number_inference() {
+    assert_eq!(encode_json(&json!(42)).unwrap(), Bson::Int32(42));
+    assert_eq!(
+        encode_json(&json!(5000000000_i64)).unwrap(),
+        Bson::Int64(5000000000)
+    );
+    assert_eq!(encode_json(&json!(3.14)).unwrap(), Bson::Double(3.14));
+    assert_eq!(encode_json(&json!(0)).unwrap(), Bson::Int32(0));
+    assert_eq!(encode_json(&json!(-0.0)).unwrap(), Bson::Double(-0.0));
+    assert_eq!(
+        encode_json(&json!(9007199254740992_i64)).unwrap(),
+        Bson::Double(9007199254740992.0)
+    );
+}
+
+#[test]
+fn markers() {
+    let value = json!({
+        "_id": {"$oid": "5f3d2a1b9c8e7d6f5a4b3c2d"},
+        "int": {"$numberInt": "-7"},
+        "long": {"$numberLong": 1565545664000_i64},
+        "created": {"$date": {"$numberLong": "-1"}},
+        "updated": {"$date": "1970-01-01T00:00:00.001Z"},
+        "re": {"$regularExpression": {"pattern": "^a", "options": "smi"}},
+        "ts": {"$timestamp": {"t": 1, "i": "2"}},
+        "uuid": {"$uuid": "c8edabc3-f738-4ca3-b68d-ab92a91478a3"},
+        "min": {"$minKey": 1},
+    });
+    let expected = doc! {
+        "_id": ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap(),
+        "int": -7_i32,
+        "long": 1565545664000_i64,
+        "created": bson::DateTime::from_millis(-1),
+        "updated": bson::DateTime::from_millis(1),
+        "re": bson::Regex { pattern: "^a".into(), options: "ims".into() },
+        "ts": bson::Timestamp { time: 1, increment: 2 },
+        "uuid": bson::Binary {
+            bytes: hex::decode("c8edabc3f7384ca3b68dab92a91478a3").unwrap(),
+            subtype: BinarySubtype::Uuid,
+        },
+        "min": Bson::MinKey,
+    };
+    assert_eq!(encode_json(&value).unwrap(), Bson::Document(expected));
+}
+
+#[test]
+fn date_shapes() {
+    let date = Bson::DateTime(bson::DateTime::from_millis(1565545664000));
+    assert_eq!(
+        encode_json(&json!({"$date": {"$numberLong": "1565545664000"}})).unwrap(),
+        date
+    );
+    assert_eq!(
+        encode_json(&json!({"$date": {"$numberLong": "+1565545664000"}})).unwrap(),
+        date
+    );
+    assert_eq!(
+        encode_json(&json!({"$date": "2019-08-11T17:47:44Z"})).unwrap(),
+        date
+    );
+    let error = encode_json(&json!({"$date": 1565545664000_i64})).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        r#"at $.$date: invalid $date: expected a string or {"$numberLong": "<millis>"}"#
+    );
+}
+
+#[test]
+fn iso_dates() {
+    let date = |iso: &str| encode_json(&json!({ "$date": iso })).unwrap();
+    let millis = |ms| Bson::DateTime(bson::DateTime::from_millis(ms));
+    // pre-epoch dates and fractional seconds
+    assert_eq!(date("1969-12-31T23:59:59.999Z"), millis(-1));
+    assert_eq!(date("1900-01-01T00:00:00Z"), millis(-2_208_988_800_000));
+    assert_eq!(date("2020-01-01T00:00:00.250Z"), millis(1_577_836_800_250));
+    assert_eq!(
+        date("2020-01-01T01:00:00.250+01:00"),
+        millis(1_577_836_800_250)
+    );
+    // not RFC 3339, years past 9999 are only read by the `%Y-%m-%dT%H:%M:%S%.fZ` fallback
+    assert_eq!(
+        date("+10000-01-01T00:00:00.5Z"),
+        millis(253_402_300_800_500)
+    );
+    let error = encode_json(&json!({"$date": "2020-01-01"})).unwrap_err();
+    assert!(
+        error.to_string().starts_with("at $.$date: invalid $date: "),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn regex_options() {
+    let regex = |options| {
+        Bson::RegularExpression(bson::Regex {
+            pattern: "^a".into(),
+            options,
+        })
+    };
+    assert_eq!(
+        encode_json(&json!({"$regularExpression": {"pattern": "^a"}})).unwrap(),
+        regex(String::new())
+    );
+    assert_eq!(
+        encode_json(&json!({"$regularExpression": {"pattern": "^a", "options": " i g "}})).unwrap(),
+        regex("gi".into())
+    );
+}
+
+#[test]
+fn binary_without_subtype_is_generic() {
+    assert_eq!(
+        encode_json(&json!({"$binary": {"base64": "aGVsbG8="}})).unwrap(),
+        Bson::Binary(bson::Binary {
+            bytes: b"hello".to_vec(),
+            subtype: BinarySubtype::Generic,
+        })
+    );
+}
+
+#[test]
+fn user_defined_binary_subtypes_survive_serialization() {
+    for (hex, byte) in [("80", 0x80), ("ff", 0xff)] {
+        let value =
+            encode_json(&json!({"b": {"$binary": {"base64": "AQID", "subType": hex}}})).unwrap();
+        let document = match value {
+            Bson::Document(document) => document,
+            other => panic!("expected a document, got {:?}", other),
+        };
+        let expected = Bson::Binary(bson::Binary {
+            bytes: vec![1, 2, 3],
+            subtype: BinarySubtype::UserDefined(byte),
+        });
+        assert_eq!(document.get("b"), Some(&expected));
+
+        let bytes = bson::to_vec(&document).unwrap();
+        // int32 length, then the element: type, "b\0", int32 size, subtype byte
+        assert_eq!(bytes[4 + 1 + 2 + 4], byte);
+        let read = bson::Document::from_reader(&bytes[..]).unwrap();
+        assert_eq!(read.get("b"), Some(&expected));
+    }
+}
+
+#[test]
+fn marker_list() {
+    assert!(EXTENDED_JSON_MARKERS
+        .iter()
+        .all(|marker| is_extended_json_marker(marker)));
+    assert!(EXTENDED_JSON_MARKERS.contains(&"$numberDecimal"));
+    assert!(!is_extended_json_marker("$set"));
+    assert!(!is_extended_json_marker("oid"));
+}
+
+#[test]
+fn db_pointer() {
+    let value =
+        json!({"$dbPointer": {"$ref": "db.users", "$id": {"$oid": "5f3d2a1b9c8e7d6f5a4b3c2d"}}});
+    assert!(matches!(encode_json(&value).unwrap(), Bson::DbPointer(_)));
+
+    let error = encode_json(&json!({"$dbPointer": {"$ref": "db.users"}})).unwrap_err();
+    assert_eq!(error.to_string(), "at $.$dbPointer: missing $dbPointer.$id");
+}
+
+#[test]
+fn dbref_keeps_canonical_order() {
+    let value = json!({"name": "x", "$db": "db", "$id": 1, "$ref": "users"});
+    let document = match encode_json(&value).unwrap() {
+        Bson::Document(document) => document,
+        other => panic!("expected a document, got {:?}", other),
+    };
+    // `Document` equality ignores the order, compare the keys
+    assert_eq!(
+        document.keys().collect::<Vec<_>>(),
+        ["$ref", "$id", "$db", "name"]
+    );
+    assert_eq!(
+        document,
+        doc! {"$ref": "users", "$id": 1, "$db": "db", "name": "x"}
+    );
+}
+
+#[test]
+fn options_apply_to_json() {
+    let value =
+        json!({"id": {"$objectId": "5f3d2a1b9c8e7d6f5a4b3c2d"}, "n": {"$numberDouble": "5.0"}});
+    let options = EncodeOptions {
+        whole_doubles_as_ints: true,
+        ..EncodeOptions::default()
+    };
+    assert_eq!(
+        encode_json_with(&value, &options).unwrap(),
+        Bson::Document(doc! {"id": {"$objectId": "5f3d2a1b9c8e7d6f5a4b3c2d"}, "n": 5})
+    );
+    let options = EncodeOptions {
+        strict_markers: true,
+        ..EncodeOptions::default()
+    };
+    let error = encode_json_with(&value, &options).unwrap_err();
+    assert_eq!(error.code(), "unknown_marker");
+    assert_eq!(error.path().as_deref(), Some("$.id"));
+}
+
+#[test]
+fn marker_next_to_plain_field_is_a_document() {
+    let value = json!({"$oid": "abc", "name": "x"});
+    assert_eq!(
+        encode_json(&value).unwrap(),
+        Bson::Document(doc! {"$oid": "abc", "name": "x"})
+    );
+}
+
+#[test]
+fn companion_keys_alone_are_documents() {
+    let cases = vec![
+        (json!({"$id": 1}), doc! {"$id": 1}),
+        (json!({"$scope": {"x": 1}}), doc! {"$scope": {"x": 1}}),
+        (json!({"$db": "test"}), doc! {"$db": "test"}),
+        (
+            json!({"$id": 1, "$db": "test"}),
+            doc! {"$id": 1, "$db": "test"},
+        ),
+    ];
+    let strict = EncodeOptions {
+        strict_markers: true,
+        ..EncodeOptions::default()
+    };
+    for (value, expected) in cases {
+        assert_eq!(
+            encode_json(&value).unwrap(),
+            Bson::Document(expected.clone())
+        );
+        assert_eq!(
+            encode_json_with(&value, &strict).unwrap(),
+            Bson::Document(expected)
+        );
+    }
+    assert_eq!(
+        encode_json(&json!({"$ref": "c", "$id": 1})).unwrap(),
+        Bson::Document(doc! {"$ref": "c", "$id": 1})
+    );
+}
+
+#[test]
+fn ref_without_id_is_a_document() {
+    let cases = vec![
+        (json!({"$ref": "x"}), doc! {"$ref": "x"}),
+        (
+            json!({"$ref": "#/definitions/a", "name": 1}),
+            doc! {"$ref": "#/definitions/a", "name": 1},
+        ),
+        (
+            json!({"$ref": "x", "$db": "db"}),
+            doc! {"$ref": "x", "$db": "db"},
+        ),
+    ];
+    for (value, expected) in cases {
+        assert_eq!(encode_json(&value).unwrap(), Bson::Document(expected));
+    }
+}
+
+#[test]
+fn uuid_layout() {
+    let uuid = |value: &str| encode_json(&json!({ "$uuid": value }));
+    assert!(matches!(
+        uuid("c8edabc3-f738-4ca3-b68d-ab92a91478a3"),
+        Ok(Bson::Binary(bson::Binary {
+            subtype: BinarySubtype::Uuid,
+            ..
+        }))
+    ));
+    for value in [
+        "c8edabc3f7384ca3b68dab92a91478a3",
+        "c8edabc3f-738-4ca3-b68d-ab92a91478a3",
+        "c8edabc3-f738-4ca3-b68d-ab92a91478a3-",
+        "-c8edabc3-f7384ca3-b68d-ab92a91478a3",
+    ] {
+        let error = uuid(value).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "at $.$uuid: invalid $uuid: expected 36 characters in the 8-4-4-4-12 hyphenated form"
+        );
+    }
+}
+
+#[test]
+fn errors() {
+    assert!(matches!(
+        encode_json(&json!({"$oid": "xyz"})),
+        Err(EncodeError::At { ref error, .. })
+            if matches!(**error, EncodeError::InvalidType { field: "$oid", .. })
+    ));
+    assert!(matches!(
+        encode_json(&json!({"$numberLong": 9007199254740993_u64})),
+        Err(EncodeError::At { ref path, ref error })
+            if path == "$.$numberLong"
+                && matches!(**error, EncodeError::InvalidValue { field: "$numberLong", .. })
+    ));
+    assert!(matches!(
+        encode_json(&json!({"$oid": "5f3d2a1b9c8e7d6f5a4b3c2d", "$date": "x"})),
+        Err(EncodeError::ConflictingMarkers(_))
+    ));
+}
+
+#[test]
+fn min_max_key_values() {
+    assert_eq!(encode_json(&json!({"$minKey": 1})).unwrap(), Bson::MinKey);
+    assert_eq!(encode_json(&json!({"$maxKey": 1})).unwrap(), Bson::MaxKey);
+    for value in [
+        json!({"$minKey": true}),
+        json!({"$maxKey": 0}),
+        json!({"$minKey": "1"}),
+    ] {
+        assert!(matches!(
+            encode_json(&value),
+            Err(EncodeError::At { ref error, .. })
+                if matches!(**error, EncodeError::InvalidType { expected: "the integer 1", .. })
+        ));
+    }
+}
+
+#[test]
+fn regex_slashes() {
+    let regex = |pattern: &str| {
+        encode_json(&json!({"$regularExpression": {"pattern": pattern, "options": ""}}))
+    };
+    assert!(regex(r"a\/b").is_ok());
+    assert!(regex(r"a\\").is_ok());
+    let error = regex(r"a\\/b").unwrap_err();
+    assert!(error.to_string().starts_with(
+        "at $.$regularExpression: invalid $regularExpression.pattern: unescaped '/' at index 3"
+    ));
+}
+
+#[test]
+fn timestamp_bounds() {
+    assert_eq!(
+        encode_json(&json!({"$timestamp": {"t": 4294967295_u64, "i": 4294967295_u64}})).unwrap(),
+        Bson::Timestamp(bson::Timestamp {
+            time: u32::MAX,
+            increment: u32::MAX,
+        })
+    );
+    for i in [
+        json!(4294967296_u64),
+        json!("4294967296"),
+        json!(-1),
+        json!(1.5),
+    ] {
+        assert!(matches!(
+            encode_json(&json!({"$timestamp": {"t": 0, "i": i}})),
+            Err(EncodeError::At { ref path, ref error })
+                if path == "$.$timestamp"
+                    && matches!(**error, EncodeError::InvalidValue { field: "$timestamp.i", .. })
+        ));
+    }
+}
+
+#[test]
+fn nested_scopes_share_the_depth_budget() {
+    let mut value = json!({});
+    for _ in 0..DEFAULT_MAX_DEPTH {
+        value = json!({"$code": "f()", "$scope": {"f": value}});
+    }
+    assert_eq!(encode_json(&value).unwrap_err().code(), "depth_exceeded");
+
+    let value = json!({"fn": {"$code": "f()", "$scope": {"f": {"$code": "g()", "$scope": {"g": {"$oid": "x"}}}}}});
+    let error = encode_json(&value).unwrap_err();
+    assert_eq!(error.path().as_deref(), Some("$.fn.$scope.f.$scope.g.$oid"));
+}
+
+#[test]
+fn errors_are_located() {
+    let value = json!({"users": [{"avatar": {"$binary": {"base64": "!!", "subType": "00"}}}]});
+    let error = encode_json(&value).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("at $.users[0].avatar.$binary: invalid base64"));
+}
+
+#[test]
+fn url_safe_base64() {
+    let binary = |base64| encode_json(&json!({"$binary": {"base64": base64, "subType": "00"}}));
+    let expected = Bson::Binary(bson::Binary {
+        bytes: vec![0xfb, 0xff, 0xbf],
+        subtype: BinarySubtype::Generic,
+    });
+    assert_eq!(binary("+/+/").unwrap(), expected);
+    assert_eq!(binary("-_-_").unwrap(), expected);
+    // mixing both alphabets is no valid base64
+    assert!(binary("+_-/").is_err());
+}
+
+#[test]
+fn decoding_errors_show_the_input() {
+    let error =
+        encode_json(&json!({"$binary": {"base64": "AQID\n", "subType": "00"}})).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .starts_with(r#"at $.$binary: invalid base64 in $binary.base64 "AQID\n": "#),
+        "{}",
+        error
+    );
+    let error = encode_json(&json!({"$uuid": "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx"})).unwrap_err();
+    assert!(
+        error.to_string().starts_with(
+            r#"at $.$uuid: invalid hex in $uuid "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxx...": "#
+        ),
+        "{}",
+        error
+    );
+}
@@ -0,0 +1,264 @@
+//! Decode every supported BSON value to its extended JSON JsValue and encode it
+//! back, the result must be the original value.
+//!
+//! Run with `wasm-pack test --node`, the encoder and decoder need a javascript runtime.
+#![cfg(target_arch = "wasm32")]
+
+use std::convert::TryFrom;
+
+use bson::{doc, oid::ObjectId, spec::BinarySubtype, Bson, Decimal128};
+use mango_bson::{
+    decode, encode, encode_with, make_binary, new_object_id, parse_object_id,
+    to_extended_json_string, EncodeOptions,
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+/// Decode and encode back, accepting the unescaped `/` stored regex patterns may
+/// hold, see `EncodeOptions::lenient_regex`
+fn round_trip(bson: Bson) -> Bson {
+    let value = decode(&bson).expect("decoding failed");
+    let options = EncodeOptions {
+        lenient_regex: true,
+        ..EncodeOptions::default()
+    };
+    encode_with(&value, &options).expect("encoding failed")
+}
+
+fn assert_round_trip(bson: Bson) {
+    assert_eq!(round_trip(bson.clone()), bson);
+}
+
+fn binary(subtype: BinarySubtype) -> Bson {
+    Bson::Binary(bson::Binary {
+        bytes: vec![0, 1, 2, 0xfe, 0xff],
+        subtype,
+    })
+}
+
+#[wasm_bindgen_test]
+fn plain_values() {
+    assert_round_trip(Bson::String(String::from("mango")));
+    assert_round_trip(Bson::Boolean(true));
+    assert_round_trip(Bson::Null);
+}
+
+#[wasm_bindgen_test]
+fn numbers() {
+    assert_round_trip(Bson::Double(1.5));
+    assert_round_trip(Bson::Double(-0.0));
+    assert_round_trip(Bson::Double(f64::INFINITY));
+    assert_round_trip(Bson::Double(f64::NEG_INFINITY));
+    assert_round_trip(Bson::Int32(i32::MIN));
+    assert_round_trip(Bson::Int32(i32::MAX));
+    assert_round_trip(Bson::Int64(i64::MIN));
+    assert_round_trip(Bson::Int64(i64::MAX));
+}
+
+#[wasm_bindgen_test]
+fn nan() {
+    // NaN never equals itself, compare the variant instead
+    match round_trip(Bson::Double(f64::NAN)) {
+        Bson::Double(n) => assert!(n.is_nan()),
+        other => panic!("expected a double, got {:?}", other),
+    }
+}
+
+#[wasm_bindgen_test]
+fn decimal() {
+    for decimal in [
+        "0",
+        "-0",
+        "1.5",
+        "-1.23E+400",
+        "1E-6176",
+        // every one of the 34 digits at the largest exponent
+        "9.999999999999999999999999999999999E+6144",
+        "Infinity",
+        "-Infinity",
+        "NaN",
+    ] {
+        assert_round_trip(Bson::Decimal128(decimal.parse::<Decimal128>().unwrap()));
+    }
+}
+
+#[wasm_bindgen_test]
+fn decimal_special_values() {
+    for (decimal, spelling) in [
+        ("NaN", "NaN"),
+        ("inf", "Infinity"),
+        ("-Infinity", "-Infinity"),
+    ] {
+        let value = decode(&Bson::Decimal128(decimal.parse::<Decimal128>().unwrap())).unwrap();
+        let spelled = js_sys::Reflect::get(&value, &"$numberDecimal".into()).unwrap();
+        assert_eq!(spelled.as_string().unwrap(), spelling);
+    }
+}
+
+#[wasm_bindgen_test]
+fn object_id() {
+    assert_round_trip(Bson::ObjectId(
+        ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap(),
+    ));
+}
+
+#[wasm_bindgen_test]
+fn dates() {
+    assert_round_trip(Bson::DateTime(bson::DateTime::from_millis(
+        1_565_545_664_123,
+    )));
+    assert_round_trip(Bson::DateTime(bson::DateTime::from_millis(0)));
+    // pre-epoch dates must not be rounded towards zero
+    assert_round_trip(Bson::DateTime(bson::DateTime::from_millis(-1)));
+    assert_round_trip(Bson::DateTime(bson::DateTime::from_millis(
+        -1_565_545_664_123,
+    )));
+    // outside of the years 0000-9999, decoded as `{"$numberLong": ...}`
+    assert_round_trip(Bson::DateTime(bson::DateTime::from_millis(i64::MIN)));
+    assert_round_trip(Bson::DateTime(bson::DateTime::from_millis(i64::MAX)));
+}
+
+#[wasm_bindgen_test]
+fn timestamp() {
+    assert_round_trip(Bson::Timestamp(bson::Timestamp {
+        time: 1_565_545_664,
+        increment: 7,
+    }));
+    assert_round_trip(Bson::Timestamp(bson::Timestamp {
+        time: u32::MAX,
+        increment: u32::MAX,
+    }));
+}
+
+#[wasm_bindgen_test]
+fn timestamp_seconds() {
+    // `t` is emitted as raw seconds, not scaled to or from milliseconds
+    let value = decode(&Bson::Timestamp(bson::Timestamp {
+        time: 1_565_545_664,
+        increment: 1,
+    }))
+    .unwrap();
+    let timestamp = js_sys::Reflect::get(&value, &"$timestamp".into()).unwrap();
+    let t = js_sys::Reflect::get(&timestamp, &"t".into()).unwrap();
+    assert_eq!(t.as_f64(), Some(1_565_545_664.0));
+}
+
+#[wasm_bindgen_test]
+fn binaries() {
+    assert_round_trip(binary(BinarySubtype::Generic));
+    assert_round_trip(binary(BinarySubtype::Function));
+    assert_round_trip(binary(BinarySubtype::BinaryOld));
+    assert_round_trip(binary(BinarySubtype::UuidOld));
+    assert_round_trip(binary(BinarySubtype::Md5));
+    assert_round_trip(binary(BinarySubtype::Encrypted));
+    assert_round_trip(binary(BinarySubtype::UserDefined(0x80)));
+    assert_round_trip(binary(BinarySubtype::UserDefined(0xff)));
+    assert_round_trip(Bson::Binary(bson::Binary {
+        bytes: vec![],
+        subtype: BinarySubtype::Generic,
+    }));
+}
+
+#[wasm_bindgen_test]
+fn made_binary() {
+    let value = make_binary(&[1, 2, 3], 6).unwrap();
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::Binary(bson::Binary {
+            bytes: vec![1, 2, 3],
+            subtype: BinarySubtype::Encrypted,
+        })
+    );
+}
+
+#[wasm_bindgen_test]
+fn object_ids() {
+    let value = parse_object_id("5F3D2A1B9C8E7D6F5A4B3C2D").unwrap();
+    assert_eq!(
+        encode(&value).unwrap(),
+        Bson::ObjectId(ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap())
+    );
+    assert!(parse_object_id("5f3d2a1b").is_err());
+
+    let fresh = encode(&new_object_id().unwrap()).unwrap();
+    assert!(matches!(fresh, Bson::ObjectId(_)));
+    assert_ne!(fresh, encode(&new_object_id().unwrap()).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn regex() {
+    assert_round_trip(Bson::RegularExpression(bson::Regex {
+        pattern: String::from("^m[a-z]+o$"),
+        options: String::from("gims"),
+    }));
+    // options come back sorted, with unknown flags dropped
+    assert_eq!(
+        round_trip(Bson::RegularExpression(bson::Regex {
+            pattern: String::from("a/b"),
+            options: String::from("smxi"),
+        })),
+        Bson::RegularExpression(bson::Regex {
+            pattern: String::from("a/b"),
+            options: String::from("ims"),
+        })
+    );
+}
+
+#[wasm_bindgen_test]
+fn code() {
+    assert_round_trip(Bson::JavaScriptCode(String::from(
+        "function() { return 1; }",
+    )));
+    assert_round_trip(Bson::JavaScriptCodeWithScope(
+        bson::JavaScriptCodeWithScope {
+            code: String::from("function() { return x + y; }"),
+            scope: doc! {
+                "x": 1,
+                "y": ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap(),
+                "z": [1.5],
+            },
+        },
+    ));
+}
+
+#[wasm_bindgen_test]
+fn canonical_extended_json_string() {
+    let relaxed = js_sys::JSON::parse(
+        r#"{"n": 1, "x": 1.5, "d": {"$date": "2020-01-01T00:00:00Z"}, "b": {"$binary": {"base64": "AQID"}}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        to_extended_json_string(&relaxed).unwrap(),
+        concat!(
+            r#"{"n":{"$numberInt":"1"},"x":{"$numberDouble":"1.5"},"#,
+            r#""d":{"$date":{"$numberLong":"1577836800000"}},"#,
+            r#""b":{"$binary":{"base64":"AQID","subType":"00"}}}"#,
+        )
+    );
+}
+
+#[wasm_bindgen_test]
+fn keys() {
+    assert_round_trip(Bson::MinKey);
+    assert_round_trip(Bson::MaxKey);
+}
+
+#[wasm_bindgen_test]
+fn deprecated_types() {
+    assert_round_trip(Bson::Symbol("mango".into()));
+    assert_round_trip(Bson::Undefined);
+    let pointer = serde_json::json!({
+        "$dbPointer": {"$ref": "db.users", "$id": {"$oid": "5f3d2a1b9c8e7d6f5a4b3c2d"}}
+    });
+    assert_round_trip(Bson::try_from(pointer).unwrap());
+}
+
+#[wasm_bindgen_test]
+fn nested() {
+    assert_round_trip(Bson::Document(doc! {
+        "_id": ObjectId::parse_str("5f3d2a1b9c8e7d6f5a4b3c2d").unwrap(),
+        "name": "mango",
+        "tags": ["a", 1_i32, 2_i64, 3.5, Bson::Null],
+        "nested": { "created": bson::DateTime::from_millis(-1), "deep": [[{}]] },
+    }));
+    assert_round_trip(Bson::Array(vec![]));
+}
@@ -4,7 +4,7 @@ export interface ObjectID {
 export interface DateTime {
   $date: {
     $numberLong: string;
-  };
+  } | string;
 }
 export interface Double {
   $numberDouble: string;
@@ -15,6 +15,9 @@ export interface Int32 {
 export interface Int64 {
   $numberLong: string;
 }
+export interface Decimal128 {
+  $numberDecimal: string;
+}
 export interface RegularExpression {
   $regularExpression: {
     pattern: string;
@@ -37,10 +40,44 @@ export enum BinarySubtype {
   Encrypted = "06",
   UserDefined = "80",
 }
+export type BinarySubtypeName =
+  | "generic"
+  | "function"
+  | "binaryOld"
+  | "uuidOld"
+  | "uuid"
+  | "md5"
+  | "encrypted"
+  | "userDefined";
 export interface Binary {
   $binary: {
     base64: string;
-    subType: BinarySubtype;
+    // generic when omitted
+    subType?: BinarySubtype | BinarySubtypeName;
+  };
+}
+export interface UUID {
+  $uuid: string;
+}
+export interface Code {
+  $code: string;
+  $scope?: BsonObject;
+}
+export interface BsonSymbol {
+  $symbol: string;
+}
+export interface Undefined {
+  $undefined: true;
+}
+export interface DBRef {
+  $ref: string;
+  $id: BsonField;
+  $db?: string;
+}
+export interface DBPointer {
+  $dbPointer: {
+    $ref: string;
+    $id: ObjectID;
   };
 }
 export interface MaxKey {
@@ -56,14 +93,27 @@ export type BsonField =
   | Double
   | Int32
   | Int64
+  | bigint
+  | Decimal128
   | RegularExpression
   | Timestamp
   | Binary
+  | UUID
+  | Code
+  | BsonSymbol
+  | Undefined
+  | DBRef
+  | DBPointer
   | MaxKey
   | MinKey
   | number
   | Date
-  | string;
+  | RegExp
+  | Uint8Array
+  | ArrayBuffer
+  | string
+  // fields holding undefined are left out of the document
+  | undefined;
 export interface BsonObject {
   [key: string]: BsonField;
 }